@@ -0,0 +1,72 @@
+//! decides per source application whether a selection is recorded. data-control never says who
+//! copied something, so rules go by the focused window and by the mime types of the offer, which
+//! password managers and the like mark with hints such as `x-kde-passwordManagerHint`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::data::CaptureRule;
+
+/// what happens to a selection a rule matches
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capture {
+  /// kept in the history like any other selection
+  #[default]
+  Record,
+  /// never read, let alone kept
+  Ignore,
+  /// kept in the history until wayclip stops, but never written to disk
+  Ephemeral,
+}
+
+/// what is known about where a selection came from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Origin {
+  /// app id of the window focused when the selection changed
+  pub app_id: Option<String>,
+  /// every mime type the selection is offered as
+  pub mime_types: Vec<String>,
+}
+
+impl CaptureRule {
+  /// whether every condition the rule has holds for `origin`
+  pub fn matches(&self, origin: &Origin) -> bool {
+    let app = match (&self.app, &origin.app_id) {
+      (None, _) => true,
+      (Some(app), Some(app_id)) => app.eq_ignore_ascii_case(app_id),
+      (Some(_), None) => false,
+    };
+    let mime = match &self.mime {
+      None => true,
+      Some(mime) => origin.mime_types.iter().any(|offered| offered.eq_ignore_ascii_case(mime)),
+    };
+
+    app && mime
+  }
+
+  /// how the rule is named on the items it matched
+  pub fn label(&self) -> String {
+    if let Some(name) = &self.name {
+      return name.clone();
+    }
+
+    let conditions = [("app", &self.app), ("mime", &self.mime)]
+      .into_iter()
+      .filter_map(|(condition, value)| Some(format!("{} {}", condition, value.as_ref()?)))
+      .collect::<Vec<_>>();
+
+    if conditions.is_empty() {
+      "everything".to_string()
+    } else {
+      conditions.join(", ")
+    }
+  }
+}
+
+/// the first rule matching `origin`
+pub fn rule<'a>(rules: &'a [CaptureRule], origin: &Origin) -> Option<&'a CaptureRule> {
+  rules.iter().find(|rule| rule.matches(origin))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,134 @@
+use super::{rule, Capture, Origin};
+use crate::{
+  clipboard::{
+    fixtures::{self, text},
+    WrappedClipboard,
+  },
+  config::{data::CaptureRule, data::Config, file::FileHelper},
+};
+
+fn rule_for(app: Option<&str>, mime: Option<&str>, action: Capture) -> CaptureRule {
+  CaptureRule {
+    name: None,
+    app: app.map(str::to_string),
+    mime: mime.map(str::to_string),
+    action,
+  }
+}
+
+fn origin(app_id: Option<&str>, mime_types: &[&str]) -> Origin {
+  Origin {
+    app_id: app_id.map(str::to_string),
+    mime_types: mime_types.iter().map(|mime| mime.to_string()).collect(),
+  }
+}
+
+fn clipboard(dir: &tempfile::TempDir, rules: Vec<CaptureRule>) -> (WrappedClipboard, FileHelper) {
+  let mut config = Config::default();
+  config.capture.rules = rules;
+
+  fixtures::clipboard(dir.path(), config)
+}
+
+#[test]
+fn rules_match_on_every_condition_they_have() {
+  let terminal = rule_for(Some("foot"), None, Capture::Ephemeral);
+  assert!(terminal.matches(&origin(Some("Foot"), &["text/plain"])));
+  assert!(!terminal.matches(&origin(Some("kitty"), &["text/plain"])));
+  assert!(!terminal.matches(&origin(None, &["text/plain"])));
+
+  let hinted = rule_for(Some("org.keepassxc.KeePassXC"), Some("x-kde-passwordManagerHint"), Capture::Ignore);
+  assert!(hinted.matches(&origin(Some("org.keepassxc.KeePassXC"), &["text/plain", "x-kde-passwordManagerHint"])));
+  assert!(!hinted.matches(&origin(Some("org.keepassxc.KeePassXC"), &["text/plain"])));
+
+  assert!(rule_for(None, None, Capture::Record).matches(&origin(None, &[])));
+}
+
+#[test]
+fn the_first_matching_rule_wins() {
+  let rules = vec![
+    rule_for(Some("foot"), None, Capture::Ephemeral),
+    rule_for(None, None, Capture::Ignore),
+  ];
+
+  assert_eq!(rule(&rules, &origin(Some("foot"), &[])).map(|rule| rule.action), Some(Capture::Ephemeral));
+  assert_eq!(rule(&rules, &origin(Some("kitty"), &[])).map(|rule| rule.action), Some(Capture::Ignore));
+  assert_eq!(rule(&[], &origin(Some("kitty"), &[])), None);
+}
+
+#[test]
+fn rules_are_labelled_by_name_or_conditions() {
+  let mut named = rule_for(Some("foot"), None, Capture::Record);
+  assert_eq!(named.label(), "app foot");
+
+  named.name = Some("terminals".to_string());
+  assert_eq!(named.label(), "terminals");
+
+  assert_eq!(rule_for(Some("a"), Some("b"), Capture::Record).label(), "app a, mime b");
+  assert_eq!(rule_for(None, None, Capture::Record).label(), "everything");
+}
+
+#[test]
+fn commit_follows_the_rules() {
+  let dir = tempfile::tempdir().unwrap();
+  let rules = vec![
+    rule_for(Some("keepassxc"), None, Capture::Ignore),
+    CaptureRule {
+      name: Some("terminals".to_string()),
+      ..rule_for(Some("foot"), None, Capture::Ephemeral)
+    },
+    rule_for(Some("firefox"), None, Capture::Record),
+  ];
+  let (clipboard, helper) = clipboard(&dir, rules);
+  let mut borrow = clipboard.write().unwrap();
+
+  borrow.commit(text("password"), &origin(Some("keepassxc"), &["text/plain"]));
+  borrow.commit(text("ls -la"), &origin(Some("foot"), &["text/plain"]));
+  borrow.commit(text("https://example.com"), &origin(Some("firefox"), &["text/plain"]));
+  borrow.commit(text("note"), &origin(None, &["text/plain"]));
+
+  let recorded = (borrow.hist.iter())
+    .map(|item| (item.id.as_str(), item.rule.as_deref(), item.ephemeral))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    recorded,
+    vec![
+      ("ls -la", Some("terminals"), true),
+      ("https://example.com", Some("app firefox"), false),
+      ("note", None, false),
+    ]
+  );
+
+  let saved = helper.retrieve_clipboard().unwrap();
+  let saved = saved.iter().map(|item| item.id.as_str()).collect::<Vec<_>>();
+  assert_eq!(saved, vec!["https://example.com", "note"]);
+}
+
+#[test]
+fn ephemeral_copies_keep_the_saved_item() {
+  let dir = tempfile::tempdir().unwrap();
+  let (clipboard, helper) = clipboard(&dir, vec![rule_for(Some("foot"), None, Capture::Ephemeral)]);
+  let mut borrow = clipboard.write().unwrap();
+  assert!(borrow.get_config().data.dedupe);
+
+  borrow.commit(text("make test"), &origin(Some("firefox"), &["text/plain"]));
+  borrow.commit(text("note"), &origin(None, &["text/plain"]));
+  borrow.commit(text("make test"), &origin(Some("foot"), &["text/plain"]));
+
+  let recorded = (borrow.hist.iter())
+    .map(|item| (item.id.as_str(), item.ephemeral))
+    .collect::<Vec<_>>();
+  assert_eq!(recorded, vec![("note", false), ("make test", false)]);
+
+  let saved = helper.retrieve_clipboard().unwrap();
+  let saved = saved.iter().map(|item| item.id.as_str()).collect::<Vec<_>>();
+  assert_eq!(saved, vec!["note", "make test"]);
+}
+
+#[test]
+fn password_managers_are_ignored_by_default() {
+  let config = Config::default();
+  let origin = origin(Some("org.keepassxc.KeePassXC"), &["text/plain", "x-kde-passwordManagerHint"]);
+
+  assert_eq!(rule(&config.capture.rules, &origin).map(|rule| rule.action), Some(Capture::Ignore));
+}
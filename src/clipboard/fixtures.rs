@@ -0,0 +1,38 @@
+//! history items and clipboards for tests, kept encrypted in a temporary directory like the real
+//! history
+
+use std::path::Path;
+
+use super::{Clipboard, Item, ItemData, TextItem, WrappedClipboard};
+use crate::config::{data::Config, file::FileHelper};
+
+/// a plain text item as a selection would be recorded
+pub fn text(text: &str) -> Item {
+  Item::new(
+    text.to_string(),
+    None,
+    ItemData::Text(TextItem {
+      text: text.to_string(),
+      mime: "text/plain".to_string(),
+    }),
+  )
+}
+
+/// a helper keeping its files in `dir`, encrypted with `key`
+pub fn helper(dir: &Path, key: &str) -> FileHelper {
+  let mut config = Config::default();
+  config.encryption.key = Some(key.to_string());
+
+  let mut helper = FileHelper::at(dir);
+  helper.init_cocoon(&config);
+
+  helper
+}
+
+/// a clipboard using `config` with its files in `dir`, and the helper reading them back
+pub fn clipboard(dir: &Path, mut config: Config) -> (WrappedClipboard, FileHelper) {
+  config.encryption.key = Some("test".to_string());
+  let helper = helper(dir, "test");
+
+  (Clipboard::init(config, helper.clone()), helper)
+}
@@ -1,20 +1,63 @@
-use std::sync::{Arc, RwLock};
-
-use crate::config::{data::Config, file::FileHelper};
+pub mod capture;
+#[cfg(test)]
+pub mod fixtures;
+#[cfg(test)]
+mod tests;
+mod thumbnail;
+
+use std::{
+  collections::{HashMap, HashSet},
+  path::PathBuf,
+  sync::{Arc, Mutex, RwLock},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  config::{data::Config, file::FileHelper},
+  search::{Candidate, SearchMode, Searcher},
+  wayland,
+};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, trace};
+use sha2::{Digest, Sha256};
+use tracing::{debug, trace, warn};
 use wayland_client::backend::ObjectId;
 
+use self::{
+  capture::{Capture, Origin},
+  thumbnail::Thumbnailer,
+};
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct TextItem {
   pub text: String,
   pub mime: String,
 }
 
+/// where the bytes of an image live - small images stay in the history file,
+/// large ones are written to their own encrypted blob keyed by `ImageItem::hash`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ImagePayload {
+  Inline(Vec<u8>),
+  Blob,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ImageItem {
-  pub image: Vec<u8>,
+  pub hash: String,
+  pub size: usize,
   pub mime: String,
+  pub payload: ImagePayload,
+}
+
+impl ImageItem {
+  pub fn new(image: Vec<u8>, mime: String) -> Self {
+    ImageItem {
+      hash: content_hash(&image),
+      size: image.len(),
+      mime,
+      payload: ImagePayload::Inline(image),
+    }
+  }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -23,25 +66,107 @@ pub enum ItemData {
   Image(ImageItem),
 }
 
+/// written to the history file field by field, so changing the fields needs a new history format
+/// version (see `config::file`)
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Item {
   pub id: String,
+  /// name of the seat the item was copied on, if the compositor told us
+  pub seat: Option<String>,
   pub data: ItemData,
+  /// pinned items are never evicted by `maxHistory`
+  pub pinned: bool,
+  /// how many times the item was picked from a menu, used to rank search results
+  pub uses: u32,
+  /// unix time the item was copied at, unknown for items from older history files
+  pub copied_at: Option<u64>,
+  /// label of the capture rule that matched when the item was copied
+  pub rule: Option<String>,
+  /// kept in memory only, by a capture rule
+  #[serde(skip)]
+  pub ephemeral: bool,
+}
+
+impl Item {
+  pub fn new(id: String, seat: Option<String>, data: ItemData) -> Self {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok();
+
+    Item {
+      id,
+      seat,
+      data,
+      pinned: false,
+      uses: 0,
+      copied_at: now.map(|now| now.as_secs()),
+      rule: None,
+      ephemeral: false,
+    }
+  }
+
+  /// names the item for as long as its content stays the same, unlike its menu index, which moves
+  /// whenever something is copied
+  pub fn key(&self) -> String {
+    let content = match &self.data {
+      ItemData::Text(text) => content_hash(text.text.as_bytes()),
+      ItemData::Image(image) => image.hash.clone(),
+    };
+
+    match self.copied_at {
+      Some(copied_at) => format!("{}@{}", content, copied_at),
+      None => content,
+    }
+  }
+}
+
+/// history layout of the first release, from before image blobs existed, kept so its history files still load
+#[derive(Deserialize)]
+pub struct LegacyItem {
+  id: String,
+  data: LegacyItemData,
+}
+
+#[derive(Deserialize)]
+enum LegacyItemData {
+  Text(TextItem),
+  Image(LegacyImageItem),
+}
+
+#[derive(Deserialize)]
+struct LegacyImageItem {
+  image: Vec<u8>,
+  mime: String,
+}
+
+impl From<LegacyItem> for Item {
+  fn from(legacy: LegacyItem) -> Self {
+    let data = match legacy.data {
+      LegacyItemData::Text(text) => ItemData::Text(text),
+      LegacyItemData::Image(image) => ItemData::Image(ImageItem::new(image.image, image.mime)),
+    };
+
+    // the first release did not record when items were copied
+    Item {
+      copied_at: None,
+      ..Item::new(legacy.id, None, data)
+    }
+  }
 }
 
 #[derive(Clone, Debug)]
 pub struct LiveClipboard {
   pub id: ObjectId,
-  pub offer: Option<wayland_protocols::ext::data_control::v1::client::ext_data_control_offer_v1::ExtDataControlOfferV1>,
+  pub seat: Option<String>,
+  pub offer: Option<wayland::Offer>,
   pub mime_types: Vec<String>,
   pub data: Vec<u8>,
   pub instant: std::time::Instant,
 }
 
 impl LiveClipboard {
-  pub fn new(id: ObjectId) -> Self {
+  pub fn new(id: ObjectId, seat: Option<String>) -> Self {
     LiveClipboard {
       id,
+      seat,
       offer: None,
       mime_types: vec![],
       data: vec![],
@@ -49,11 +174,7 @@ impl LiveClipboard {
     }
   }
 
-  pub fn handle_offer(
-    &mut self,
-    offer: &wayland_protocols::ext::data_control::v1::client::ext_data_control_offer_v1::ExtDataControlOfferV1,
-    mime_type: String,
-  ) {
+  pub fn handle_offer(&mut self, offer: &wayland::Offer, mime_type: String) {
     self.mime_types.push(mime_type);
 
     if self.offer.is_none() {
@@ -64,45 +185,102 @@ impl LiveClipboard {
 
 #[derive(Clone, Debug)]
 pub struct Clipboard {
-  pub live: Option<LiveClipboard>,
+  /// offers that have been announced but not yet claimed by a selection event
+  pub live: HashMap<ObjectId, LiveClipboard>,
   pub hist: Vec<Item>,
 
   // private
   config: Config,
   helper: FileHelper,
+  /// image sizes by content hash, worked out the first time a menu needs them
+  dimensions: Arc<Mutex<HashMap<String, Dimensions>>>,
+  /// unset when thumbnails are turned off
+  thumbnailer: Option<Thumbnailer>,
+  /// set once the daemon is stopping, or for a copy opened next to it, after which nothing is
+  /// recorded or written
+  closed: bool,
 }
 
+/// width and height of an image, if its header could be read
+pub type Dimensions = Option<(usize, usize)>;
+
 pub type WrappedClipboard = Arc<RwLock<Clipboard>>;
 
 impl Clipboard {
   pub fn init(config: Config, helper: FileHelper) -> WrappedClipboard {
+    let mut cb = Clipboard::new(config, helper);
+
+    if cb.config.thumbnails.enabled {
+      cb.thumbnailer = Thumbnailer::spawn(cb.helper.clone(), cb.config.thumbnails.size);
+    }
+
+    cb.restore();
+    // whatever a crash left behind
+    cb.collect_blobs();
+
+    Arc::new(RwLock::new(cb))
+  }
+
+  /// the saved history, for commands that may run next to the daemon. nothing is written and no
+  /// thumbnails are made, so the daemon's files are left alone
+  pub fn open(config: Config, helper: FileHelper) -> Self {
     let mut cb = Clipboard {
-      live: None,
+      closed: true,
+      ..Clipboard::new(config, helper)
+    };
+    cb.restore();
+
+    cb
+  }
+
+  fn new(config: Config, helper: FileHelper) -> Self {
+    Clipboard {
+      live: HashMap::new(),
       hist: vec![],
 
       // private
       config,
       helper,
-    };
-
-    cb.restore();
+      dimensions: Arc::new(Mutex::new(HashMap::new())),
+      thumbnailer: None,
+      closed: false,
+    }
+  }
 
-    Arc::new(RwLock::new(cb))
+  pub fn new_offer(&mut self, id: ObjectId, seat: Option<String>) {
+    self.live.insert(id.clone(), LiveClipboard::new(id, seat));
   }
 
-  pub fn new_offer(&mut self, id: ObjectId) {
-    self.live = Some(LiveClipboard::new(id));
+  /// what the capture rules say to do with a selection from `origin`
+  pub fn capture(&self, origin: &Origin) -> Capture {
+    capture::rule(&self.config.capture.rules, origin).map_or(Capture::Record, |rule| rule.action)
   }
 
-  pub fn commit(&mut self, data: Item) {
+  /// record a selection from `origin`, as the capture rules allow
+  pub fn commit(&mut self, mut data: Item, origin: &Origin) {
+    if self.closed {
+      debug!("not recording a selection, wayclip is stopping");
+      return;
+    }
+
     if let Some(last) = self.hist.last() {
       match (last.clone().data, data.clone().data) {
         (ItemData::Text(text), ItemData::Text(new_text)) if text.text == new_text.text => return,
-        (ItemData::Image(image), ItemData::Image(new_image)) if image.image == new_image.image => return,
+        (ItemData::Image(image), ItemData::Image(new_image)) if image.hash == new_image.hash => return,
         _ => {}
       }
     }
 
+    if let Some(rule) = capture::rule(&self.config.capture.rules, origin) {
+      if rule.action == Capture::Ignore {
+        debug!("not recording a selection, the capture rule {:?} ignores it", rule.label());
+        return;
+      }
+
+      data.rule = Some(rule.label());
+      data.ephemeral = rule.action == Capture::Ephemeral;
+    }
+
     if self.config.data.dedupe {
       let timer = std::time::Instant::now();
 
@@ -111,10 +289,20 @@ impl Clipboard {
         .iter()
         .position(|item| match (item.clone().data, data.clone().data) {
           (ItemData::Text(text), ItemData::Text(new_text)) if text.text == new_text.text => true,
-          (ItemData::Image(image), ItemData::Image(new_image)) if image.image == new_image.image => true,
+          (ItemData::Image(image), ItemData::Image(new_image)) if image.hash == new_image.hash => true,
           _ => false,
         });
 
+      // an ephemeral copy of something already saved keeps the saved item, moved to the front, so
+      // the content does not disappear from disk
+      if let Some(idx) = idx.filter(|idx| data.ephemeral && !self.hist[*idx].ephemeral) {
+        debug!("found saved duplicate of an ephemeral clipboard item - keeping it");
+        let item = self.hist.remove(idx);
+        self.hist.push(item);
+        self.save();
+        return;
+      }
+
       if let Some(idx) = idx {
         debug!("found duplicate clipboard item - removing");
         self.hist.remove(idx);
@@ -123,13 +311,114 @@ impl Clipboard {
       trace!("(copy function) deduped clipboard in {:?}", timer.elapsed());
     }
 
+    self.externalize(&mut data);
+    self.request_thumbnail(&data);
+
     self.hist.push(data);
-    if self.hist.len() > self.config.general.max_history && self.config.general.max_history > 0 {
-      self.hist.remove(0);
+
+    if self.trim() {
+      self.save_dropped();
+    } else {
+      self.save();
     }
+  }
 
-    self.live = None;
-    self.save();
+  /// switch to a newly loaded config, applying whatever changed to the history as it is. the old
+  /// thumbnail worker is stopped with the clipboard unlocked, as it may be busy with a large image
+  pub fn reload(clipboard: &WrappedClipboard, config: Config) {
+    let mut borrow = clipboard.write().unwrap();
+    if config.encryption.key != borrow.config.encryption.key
+      || config.encryption.encrypt != borrow.config.encryption.encrypt
+    {
+      warn!("encryption settings only change when wayclip restarts");
+    }
+
+    let thumbnails = (config.thumbnails.enabled, config.thumbnails.size);
+    let resized = thumbnails != (borrow.config.thumbnails.enabled, borrow.config.thumbnails.size);
+    let retired = if resized { borrow.thumbnailer.take() } else { None };
+
+    borrow.config = config;
+    if borrow.trim() {
+      borrow.save_dropped();
+    }
+    drop(borrow);
+
+    if resized {
+      // thumbnails of the old size are made again, once the old worker can no longer write any
+      if let Some(thumbnailer) = retired {
+        thumbnailer.stop();
+      }
+
+      let mut borrow = clipboard.write().unwrap();
+      borrow.helper.collect_thumbnails(&HashSet::new());
+      borrow.thumbnailer = (borrow.config.thumbnails.enabled)
+        .then(|| Thumbnailer::spawn(borrow.helper.clone(), borrow.config.thumbnails.size))
+        .flatten();
+    }
+
+    let borrow = clipboard.read().unwrap();
+    for item in &borrow.hist {
+      borrow.request_thumbnail(item);
+    }
+  }
+
+  /// drop the oldest items that are not pinned until the history fits `maxHistory`. returns
+  /// whether anything was dropped
+  fn trim(&mut self) -> bool {
+    let max = self.config.general.max_history;
+    let mut trimmed = false;
+
+    while max > 0 && self.hist.len() > max {
+      let Some(idx) = self.hist.iter().position(|item| !item.pinned) else {
+        break;
+      };
+
+      self.hist.remove(idx);
+      trimmed = true;
+    }
+
+    trimmed
+  }
+
+  /// load the bytes of an image, reading its blob from disk if it is not stored inline
+  pub fn image_bytes(&self, image: &ImageItem) -> Option<Vec<u8>> {
+    match &image.payload {
+      ImagePayload::Inline(bytes) => Some(bytes.clone()),
+      ImagePayload::Blob => self.helper.retrieve_blob(&image.hash),
+    }
+  }
+
+  /// width and height of an image, read from its header
+  pub fn image_dimensions(&self, image: &ImageItem) -> Dimensions {
+    if let Some(dimensions) = self.dimensions.lock().unwrap().get(&image.hash) {
+      return *dimensions;
+    }
+
+    let dimensions = (self.image_bytes(image))
+      .and_then(|bytes| imagesize::blob_size(&bytes).ok())
+      .map(|size| (size.width, size.height));
+
+    self.dimensions.lock().unwrap().insert(image.hash.clone(), dimensions);
+    dimensions
+  }
+
+  /// path of the png thumbnail of an image, once the background worker has made it
+  pub fn thumbnail(&self, image: &ImageItem) -> Option<PathBuf> {
+    self.thumbnailer.as_ref()?.get(image)
+  }
+
+  fn request_thumbnail(&self, item: &Item) {
+    if let (Some(thumbnailer), ItemData::Image(image)) = (&self.thumbnailer, &item.data) {
+      thumbnailer.request(image);
+    }
+  }
+
+  /// the bytes and mime type to put back on the clipboard for a menu index
+  pub fn selection(&self, idx: usize) -> Option<(Vec<u8>, String)> {
+    match &self.item_at(idx)?.data {
+      ItemData::Text(text) => Some((text.text.clone().into_bytes(), self.preferred_text_mime())),
+      ItemData::Image(image) => Some((self.image_bytes(image)?, image.mime.clone())),
+    }
   }
 
   pub fn preferred_text_mime(&self) -> String {
@@ -146,25 +435,129 @@ impl Clipboard {
 
   pub fn clear(&mut self) {
     self.hist.clear();
-    self.save()
+    self.save_dropped();
   }
 
-  /// handle a clipboard paste event by moving the selected index to the end
-  pub fn pasted_idx(&mut self, idx: usize) {
-    if idx >= self.hist.len() {
+  /// handle a clipboard paste event by moving the item `key` to the end
+  pub fn pasted(&mut self, key: &str) {
+    let Some(pos) = self.hist.iter().rposition(|item| item.key() == key) else {
       return;
+    };
+
+    let mut item = self.hist.remove(pos);
+    item.uses = item.uses.saturating_add(1);
+    self.hist.push(item);
+    self.save();
+  }
+
+  /// the menu index the item `key` has now. `idx` is where a client saw it, and wins among items
+  /// with the same key
+  pub fn locate(&self, idx: usize, key: &str) -> Option<usize> {
+    if self.item_at(idx).is_some_and(|item| item.key() == key) {
+      return Some(idx);
     }
 
-    // remove the item from history reversed
-    let item = self.hist.remove(self.hist.len() - idx - 1);
+    self.hist.iter().rev().position(|item| item.key() == key)
+  }
+
+  /// the item at a menu index (newest first)
+  pub fn item_at(&self, idx: usize) -> Option<&Item> {
+    self.position(idx).map(|pos| &self.hist[pos])
+  }
+
+  pub fn remove_idx(&mut self, idx: usize) {
+    let Some(pos) = self.position(idx) else {
+      return;
+    };
+
+    self.hist.remove(pos);
+    self.save_dropped();
+  }
+
+  pub fn toggle_pin_idx(&mut self, idx: usize) {
+    let Some(pos) = self.position(idx) else {
+      return;
+    };
+
+    self.hist[pos].pinned = !self.hist[pos].pinned;
+    self.save();
+  }
+
+  /// swap the text of the item `id` for `text`, as long as it still holds `original`, and make it
+  /// the newest item. returns its new key, or nothing if the item is gone or changed in the meantime
+  pub fn replace_text(&mut self, id: &str, original: &str, text: String) -> Option<String> {
+    let pos = self.hist.iter().rposition(|item| match &item.data {
+      ItemData::Text(old) => item.id == id && old.text == original,
+      ItemData::Image(_) => false,
+    });
+    let pos = pos?;
+
+    let mut item = self.hist.remove(pos);
+    if let ItemData::Text(old) = &mut item.data {
+      old.text = text;
+    }
+    let key = item.key();
     self.hist.push(item);
     self.save();
+
+    Some(key)
+  }
+
+  /// a file in the private runtime directory for short lived working copies, if there is one
+  pub fn scratch_path(&self, name: &str) -> Option<PathBuf> {
+    Some(self.helper.runtime_dir.as_ref()?.join(name))
+  }
+
+  /// menu indexes of the items matching `query`, best first
+  pub fn search(&self, query: &str, mode: SearchMode) -> Vec<usize> {
+    let candidates = (self.hist.iter().rev().enumerate()).map(|(idx, item)| Candidate::from_item(idx, item));
+
+    Searcher::new(mode).search(query, candidates)
+  }
+
+  /// menus index history newest first, this maps that back to a position in `hist`
+  fn position(&self, idx: usize) -> Option<usize> {
+    (idx < self.hist.len()).then(|| self.hist.len() - idx - 1)
+  }
+
+  /// move a large inline image into its own blob file. ephemeral images stay in memory
+  fn externalize(&self, item: &mut Item) {
+    let image = match &mut item.data {
+      ItemData::Image(image) if !item.ephemeral => image,
+      _ => return,
+    };
+
+    let bytes = match &image.payload {
+      ImagePayload::Inline(bytes) if bytes.len() > self.config.data.blob_threshold => bytes,
+      _ => return,
+    };
+
+    let timer = std::time::Instant::now();
+
+    self.helper.persist_blob(&image.hash, bytes);
+    image.payload = ImagePayload::Blob;
+
+    trace!("stored {:?} byte image as blob in {:?}", image.size, timer.elapsed());
+  }
+
+  /// write the history one last time before wayclip stops. selections read after this are dropped,
+  /// so nothing can start another write while the process exits. returns the thumbnail worker, to
+  /// stop once the clipboard is unlocked
+  pub fn close(&mut self) -> Option<Thumbnailer> {
+    self.save();
+    self.closed = true;
+
+    self.thumbnailer.take()
   }
 
   fn save(&self) {
+    if self.closed {
+      return;
+    }
+
     let timer = std::time::Instant::now();
 
-    let savable = self.hist.clone();
+    let savable = self.hist.iter().filter(|item| !item.ephemeral).cloned().collect::<Vec<_>>();
 
     debug!("persisting {:?} clipboard items", savable.len());
     self.helper.persist_clipboard(savable);
@@ -172,6 +565,41 @@ impl Clipboard {
     trace!("persisted clipboard in {:?}", timer.elapsed());
   }
 
+  /// save after items left the history, then remove the files only they used
+  fn save_dropped(&self) {
+    self.save();
+    self.collect_blobs();
+  }
+
+  /// remove blob files and thumbnails that no history entry references anymore
+  fn collect_blobs(&self) {
+    if self.closed {
+      return;
+    }
+
+    let images = self.hist.iter().filter_map(|item| match &item.data {
+      ItemData::Image(image) => Some(image),
+      ItemData::Text(_) => None,
+    });
+
+    let referenced = (images.clone())
+      .filter(|image| image.payload == ImagePayload::Blob)
+      .map(|image| image.hash.clone())
+      .collect::<HashSet<_>>();
+
+    let removed = self.helper.collect_blobs(&referenced);
+    if removed > 0 {
+      debug!("removed {:?} unreferenced blobs", removed);
+    }
+
+    let referenced = images.map(|image| image.hash.clone()).collect::<HashSet<_>>();
+
+    let removed = self.helper.collect_thumbnails(&referenced);
+    if removed > 0 {
+      debug!("removed {:?} unreferenced thumbnails", removed);
+    }
+  }
+
   fn restore(&mut self) {
     let timer = std::time::Instant::now();
 
@@ -189,6 +617,15 @@ impl Clipboard {
         trace!("(restore function) image removal took {:?}", timer.elapsed());
       }
 
+      // histories written before blobs existed (or with a higher threshold) keep large images inline.
+      // a copy opened next to the daemon leaves them as they are
+      if !self.closed {
+        for item in existing.iter_mut() {
+          self.externalize(item);
+          self.request_thumbnail(item);
+        }
+      }
+
       if !self.config.data.dedupe {
         self.hist = existing;
       } else {
@@ -200,8 +637,7 @@ impl Clipboard {
           .into_iter()
           .unique_by(|item| match item.data.clone() {
             ItemData::Text(text) => text.text,
-            // no good way to dedupe images with unique_by bc of clones - hashing maybe but that's a lot of work
-            ItemData::Image(_) => item.id.clone(),
+            ItemData::Image(image) => image.hash,
           })
           .collect();
 
@@ -214,3 +650,8 @@ impl Clipboard {
     trace!("restored clipboard in {:?}", timer.elapsed());
   }
 }
+
+/// hex encoded sha256 of some clipboard content, used to address blobs and dedupe images
+pub fn content_hash(bytes: &[u8]) -> String {
+  Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,138 @@
+use super::{capture::Origin, fixtures, Clipboard, ImageItem, Item, ItemData};
+use crate::config::data::Config;
+
+#[test]
+fn locate_follows_an_item_as_the_history_changes() {
+  let dir = tempfile::tempdir().unwrap();
+  let (clipboard, _) = fixtures::clipboard(dir.path(), Config::default());
+  let mut borrow = clipboard.write().unwrap();
+
+  for text in ["first", "second", "third"] {
+    borrow.commit(fixtures::text(text), &Origin::default());
+  }
+  let key = borrow.item_at(2).unwrap().key();
+  assert_eq!(borrow.locate(2, &key), Some(2));
+
+  // copied while a menu was open
+  borrow.commit(fixtures::text("fourth"), &Origin::default());
+  assert_eq!(borrow.locate(2, &key), Some(3));
+  assert_eq!(borrow.item_at(3).unwrap().id, "first");
+
+  borrow.remove_idx(3);
+  assert_eq!(borrow.locate(2, &key), None);
+}
+
+#[test]
+fn pasted_moves_the_item_by_key() {
+  let dir = tempfile::tempdir().unwrap();
+  let (clipboard, _) = fixtures::clipboard(dir.path(), Config::default());
+  let mut borrow = clipboard.write().unwrap();
+
+  borrow.commit(fixtures::text("first"), &Origin::default());
+  borrow.commit(fixtures::text("second"), &Origin::default());
+  let key = borrow.item_at(1).unwrap().key();
+
+  borrow.pasted(&key);
+
+  let newest = borrow.item_at(0).unwrap();
+  assert_eq!((newest.id.as_str(), newest.uses), ("first", 1));
+}
+
+#[test]
+fn nothing_is_kept_on_disk_without_a_runtime_dir() {
+  let dir = tempfile::tempdir().unwrap();
+  let (_, mut helper) = fixtures::clipboard(dir.path(), Config::default());
+  helper.runtime_dir = None;
+
+  let clipboard = Clipboard::init(Config::default(), helper);
+  let borrow = clipboard.read().unwrap();
+
+  assert!(borrow.get_config().thumbnails.enabled);
+  assert!(borrow.thumbnailer.is_none());
+  assert_eq!(borrow.scratch_path("edit.txt"), None);
+}
+
+#[test]
+fn replaced_text_is_found_by_its_new_key() {
+  let dir = tempfile::tempdir().unwrap();
+  let (clipboard, _) = fixtures::clipboard(dir.path(), Config::default());
+  let mut borrow = clipboard.write().unwrap();
+
+  borrow.commit(fixtures::text("typo"), &Origin::default());
+  let key = borrow.replace_text("typo", "typo", "fixed".to_string()).unwrap();
+  // copied while the editor was open
+  borrow.commit(fixtures::text("newer"), &Origin::default());
+
+  let index = borrow.locate(0, &key).unwrap();
+  assert_eq!(borrow.selection(index).unwrap().0, b"fixed");
+  assert_eq!(borrow.replace_text("typo", "typo", "again".to_string()), None);
+}
+
+#[test]
+fn resized_thumbnails_replace_the_old_ones() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut config = Config::default();
+  config.thumbnails.size = 64;
+  let (clipboard, _) = fixtures::clipboard(dir.path(), config.clone());
+
+  let mut png = std::io::Cursor::new(vec![]);
+  image::RgbImage::new(320, 200).write_to(&mut png, image::ImageFormat::Png).unwrap();
+  let image = ImageItem::new(png.into_inner(), "image/png".to_string());
+  let item = Item::new("image".to_string(), None, ItemData::Image(image.clone()));
+  clipboard.write().unwrap().commit(item, &Origin::default());
+
+  let thumbnail_size = || {
+    let path = clipboard.read().unwrap().thumbnail(&image)?;
+    image::image_dimensions(path).ok()
+  };
+  assert!(wait_until(|| thumbnail_size() == Some((64, 40))));
+
+  config.thumbnails.size = 32;
+  Clipboard::reload(&clipboard, config);
+
+  assert!(wait_until(|| thumbnail_size() == Some((32, 20))));
+  let thumbnails = std::fs::read_dir(dir.path().join("runtime/thumbnails")).unwrap().count();
+  assert_eq!(thumbnails, 1);
+}
+
+#[test]
+fn blobs_are_only_collected_when_items_leave() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut config = Config::default();
+  config.general.max_history = 2;
+  config.data.blob_threshold = 0;
+  let (clipboard, helper) = fixtures::clipboard(dir.path(), config.clone());
+
+  let image = ImageItem::new(vec![1, 2, 3], "image/png".to_string());
+  let item = Item::new("image".to_string(), None, ItemData::Image(image.clone()));
+  clipboard.write().unwrap().commit(item, &Origin::default());
+
+  let blobs = dir.path().join("cache/blobs");
+  let stray = blobs.join("stray");
+  std::fs::write(&stray, b"left by a crash").unwrap();
+
+  // a copy opened next to the daemon writes nothing
+  let copy = Clipboard::open(config, helper);
+  assert_eq!(copy.hist.len(), 1);
+  drop(copy);
+  assert!(stray.is_file());
+
+  clipboard.write().unwrap().commit(fixtures::text("kept"), &Origin::default());
+  assert!(stray.is_file() && blobs.join(&image.hash).is_file());
+
+  clipboard.write().unwrap().commit(fixtures::text("evicts the image"), &Origin::default());
+  assert!(!stray.exists() && !blobs.join(&image.hash).exists());
+}
+
+/// poll for a few seconds, thumbnails are made on another thread
+fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+  let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+  while std::time::Instant::now() < deadline {
+    if condition() {
+      return true;
+    }
+    std::thread::sleep(std::time::Duration::from_millis(10));
+  }
+
+  condition()
+}
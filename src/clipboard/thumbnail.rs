@@ -0,0 +1,124 @@
+//! small png previews of image items for menus that can show icons. they are made on a worker
+//! thread so a large screenshot never holds up capturing the clipboard.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Sender},
+    Arc, Mutex,
+  },
+  thread::JoinHandle,
+};
+
+use image::{imageops::FilterType, ImageFormat};
+use tracing::{debug, trace, warn};
+
+use super::{ImageItem, ImagePayload};
+use crate::config::file::FileHelper;
+
+#[derive(Clone, Debug)]
+pub struct Thumbnailer {
+  jobs: Sender<Job>,
+  dir: PathBuf,
+  /// set by `stop`, after which queued images are skipped
+  stopped: Arc<AtomicBool>,
+  worker: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+enum Job {
+  Render(ImageItem),
+  /// wakes the worker up to see that it was stopped
+  Stop,
+}
+
+impl Thumbnailer {
+  /// start the worker, making thumbnails no larger than `size` pixels on either side. nothing is
+  /// started without a runtime directory to keep them in
+  pub fn spawn(helper: FileHelper, size: u32) -> Option<Self> {
+    let Some(dir) = helper.thumbnail_dir() else {
+      warn!("no thumbnails without $XDG_RUNTIME_DIR, they would outlive the session unencrypted");
+      return None;
+    };
+    let (jobs, receiver) = mpsc::channel::<Job>();
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let (t_dir, t_stopped) = (dir.clone(), stopped.clone());
+    let worker = std::thread::spawn(move || {
+      for job in receiver {
+        let image = match job {
+          Job::Render(image) if !t_stopped.load(Ordering::SeqCst) => image,
+          _ => break,
+        };
+
+        let path = path(&t_dir, &image);
+        if path.is_file() {
+          continue;
+        }
+
+        let timer = std::time::Instant::now();
+        match render(&helper, &image, size, path) {
+          Some(path) => trace!("made thumbnail {:?} in {:?}", path, timer.elapsed()),
+          None => debug!("could not make a thumbnail for {} image {}", image.mime, image.hash),
+        }
+      }
+    });
+
+    Some(Thumbnailer {
+      jobs,
+      dir,
+      stopped,
+      worker: Arc::new(Mutex::new(Some(worker))),
+    })
+  }
+
+  /// stop the worker and wait for it, so no thumbnail is written once this returns
+  pub fn stop(&self) {
+    self.stopped.store(true, Ordering::SeqCst);
+    let _ = self.jobs.send(Job::Stop);
+
+    if let Some(worker) = self.worker.lock().unwrap().take() {
+      let _ = worker.join();
+    }
+  }
+
+  /// make a thumbnail for `image` in the background unless there already is one
+  pub fn request(&self, image: &ImageItem) {
+    if path(&self.dir, image).is_file() {
+      return;
+    }
+
+    if self.jobs.send(Job::Render(image.clone())).is_err() {
+      warn!("thumbnail worker is gone");
+    }
+  }
+
+  /// the thumbnail of `image`, if it has been made yet
+  pub fn get(&self, image: &ImageItem) -> Option<PathBuf> {
+    Some(path(&self.dir, image)).filter(|path| path.is_file())
+  }
+}
+
+fn path(dir: &Path, image: &ImageItem) -> PathBuf {
+  dir.join(format!("{}.png", image.hash))
+}
+
+fn render(helper: &FileHelper, image: &ImageItem, size: u32, path: PathBuf) -> Option<PathBuf> {
+  let bytes = match &image.payload {
+    ImagePayload::Inline(bytes) => bytes.clone(),
+    ImagePayload::Blob => helper.retrieve_blob(&image.hash)?,
+  };
+
+  let mut thumbnail = image::load_from_memory(&bytes).ok()?;
+  if thumbnail.width() > size || thumbnail.height() > size {
+    thumbnail = thumbnail.resize(size, size, FilterType::Triangle);
+  }
+
+  // write then rename so a menu never picks up half a png
+  let tmp = path.with_extension("tmp");
+  thumbnail.save_with_format(&tmp, ImageFormat::Png).ok()?;
+  fs::rename(&tmp, &path).ok()?;
+
+  Some(path)
+}
@@ -1,11 +1,24 @@
 use interprocess::local_socket::{LocalSocketListener, LocalSocketStream, NameTypeSupport};
-use std::io::{self, prelude::*, BufReader};
+use serde::{Deserialize, Serialize};
+use std::{
+  fs, io,
+  path::PathBuf,
+  sync::{Arc, Mutex},
+};
 use tracing::{debug, trace, warn};
 
-use crate::{clipboard, menu};
+use crate::{
+  clipboard,
+  input::{PasteMethod, WrappedPasteMethod},
+  menu::{self, Action, Chosen},
+  search::SearchMode,
+  shutdown::Shutdown,
+};
+
+/// how much of a text item clients get to list and search
+const PREVIEW_CHARS: usize = 4096;
 
 pub struct SocketHandler {
-  buffer: String,
   socket: SocketType,
 }
 
@@ -14,13 +27,109 @@ pub enum SocketType {
   Client(LocalSocketStream),
 }
 
-pub type MPSCMessage = (String, usize);
+/// what clients can ask of the daemon. indexes are menu indexes, newest item first, and come with the
+/// item's key so the daemon still finds the right item after the history changed
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+  Toggle {
+    pid: u32,
+    seat: Option<String>,
+    /// only offer items matching this in the menu
+    query: Option<String>,
+    /// type the chosen item out instead of pasting it
+    type_out: bool,
+  },
+  List,
+  /// history items matching `query`, best first. `mode` defaults to the configured one
+  Search { query: String, mode: Option<SearchMode> },
+  /// do `action` with the item at `index`, as if it had been chosen that way from a menu
+  Act {
+    index: usize,
+    key: String,
+    action: Action,
+    seat: Option<String>,
+  },
+  Status,
+  /// forget the whole history
+  Clear,
+  /// save the history and stop the daemon
+  Quit,
+}
+
+/// the daemon's answer to anything but `Request::Toggle`
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+  Entries(Vec<Entry>),
+  Status(Status),
+  Done,
+  /// the request could not be carried out
+  Error(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+  pub menu: String,
+  pub paste_method: PasteMethod,
+  pub items: usize,
+  pub pinned: usize,
+}
+
+/// a history item as seen by clients
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+  pub index: usize,
+  /// `Item::key` of the item, to send back with its index
+  pub key: String,
+  pub pinned: bool,
+  pub uses: u32,
+  pub seat: Option<String>,
+  pub copied_at: Option<u64>,
+  pub content: EntryContent,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EntryContent {
+  /// the start of the text, at most `PREVIEW_CHARS` characters. the full text stays with the daemon
+  /// until the item is acted on
+  Text { preview: String, size: usize },
+  Image {
+    mime: String,
+    size: usize,
+    dimensions: Option<(usize, usize)>,
+    thumbnail: Option<PathBuf>,
+  },
+}
+
+/// a menu selection handed to the wayland side to copy and paste
+pub struct MPSCMessage {
+  pub data: Vec<u8>,
+  pub mime: String,
+  /// whether the item is text, the only kind that can be typed out. `mime` may be anything the
+  /// config names, like `UTF8_STRING`
+  pub text: bool,
+  /// `Item::key` of the item, to count the use once it is copied
+  pub key: String,
+  /// seat to copy onto: the one asked for, else the one the item was copied on, else every seat
+  pub seat: Option<String>,
+  /// one of the actions that put the item on the clipboard
+  pub action: Action,
+}
 
 impl SocketHandler {
   pub fn server() -> Self {
     let name = get_socket_name();
 
     let socket = match LocalSocketListener::bind(name) {
+      // a daemon that was killed leaves its socket file behind
+      Err(e) if e.kind() == io::ErrorKind::AddrInUse && is_stale(name) => {
+        debug!("removing stale socket {}", name);
+        remove_socket(name);
+        LocalSocketListener::bind(name)
+      }
+      x => x,
+    };
+
+    let socket = match socket {
       Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
         eprintln!("socket already in use, please check that wayclip is not already running");
         std::process::exit(1);
@@ -31,75 +140,340 @@ impl SocketHandler {
     debug!("server socket opened at {}", name);
 
     Self {
-      buffer: String::with_capacity(128),
       socket: SocketType::Server(socket),
     }
   }
 
   pub fn client() -> Self {
+    Self::try_client().unwrap_or_else(|| {
+      eprintln!("wayclip server is not running, please start it first");
+      std::process::exit(1);
+    })
+  }
+
+  /// connect to the daemon, if it is running
+  pub fn try_client() -> Option<Self> {
     let name = get_socket_name();
 
     let socket = match LocalSocketStream::connect(name) {
-      Err(e) if e.kind() == io::ErrorKind::NotFound => {
-        eprintln!("wayclip server is not running, please start it first");
-        std::process::exit(1);
-      }
+      Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => return None,
       x => x.unwrap(),
     };
 
     debug!("client socket opened at {}", name);
 
-    Self {
-      buffer: String::with_capacity(128),
+    Some(Self {
       socket: SocketType::Client(socket),
-    }
+    })
   }
 
+  /// serve clients until a shutdown is requested. each connection gets its own thread, so an open menu
+  /// does not hold up `status`, `quit` and the rest
   pub fn listen(
     &mut self,
     clipboard: clipboard::WrappedClipboard,
     menu_message_sender: std::sync::mpsc::Sender<MPSCMessage>,
+    paste_method: WrappedPasteMethod,
+    shutdown: Shutdown,
   ) {
-    let menu = menu::init(clipboard).expect("failed to initialize a menu backend");
+    let menu = menu::init(clipboard.clone()).expect("failed to initialize a menu backend");
+    let menu_name = clipboard.read().unwrap().get_config().general.menu;
+
+    let server = Server {
+      clipboard,
+      sender: menu_message_sender,
+      paste_method,
+      shutdown,
+      menu: Arc::new(Mutex::new((menu, menu_name))),
+    };
 
     match &mut self.socket {
       SocketType::Server(listener) => {
         for conn in listener.incoming().filter_map(handle_error) {
-          let mut conn = BufReader::new(conn);
-          conn.read_line(&mut self.buffer).unwrap();
-          debug!("server got toggle from client pid: {}", self.buffer);
+          // woken up by `stop`
+          if server.shutdown.requested() {
+            break;
+          }
 
-          let result = menu.show();
-
-          let data = match result {
-            Ok(Some(data)) => data,
-            Ok(None) => continue,
-            Err(_) => continue,
-          };
-
-          debug!("selected: \"{:?}\" from menu of index \"{:?}\"", &data.0, &data.1);
-          menu_message_sender.send(data).unwrap();
-          trace!("message sent to clipboard handler");
-
-          self.buffer.clear();
-          trace!("buffer cleared for next client");
+          let server = server.clone();
+          std::thread::spawn(move || server.serve(conn));
         }
       }
       SocketType::Client(_) => panic!("Client cannot listen"),
     }
   }
 
-  pub fn toggle(&mut self) {
+  pub fn toggle(&mut self, seat: Option<String>, query: Option<String>, type_out: bool) {
     match &mut self.socket {
       SocketType::Client(conn) => {
         let pid = std::process::id();
         debug!("my (client) pid is {} and i am going to message the server", pid);
 
-        conn.write_all(format!("{}", pid).as_bytes()).unwrap();
+        let request = Request::Toggle {
+          pid,
+          seat,
+          query,
+          type_out,
+        };
+        bincode::serialize_into(conn, &request).unwrap();
       }
       SocketType::Server(_) => panic!("Server cannot toggle"),
     }
   }
+
+  /// wake a listener waiting for clients so it sees the shutdown, and remove the socket file
+  pub fn stop() {
+    let name = get_socket_name();
+    let _ = LocalSocketStream::connect(name);
+    remove_socket(name);
+  }
+
+  /// send a request and wait for the daemon to answer it
+  pub fn request(&mut self, request: Request) -> Option<Response> {
+    match &mut self.socket {
+      SocketType::Client(conn) => {
+        bincode::serialize_into(&mut *conn, &request).ok()?;
+
+        match bincode::deserialize_from(conn) {
+          Ok(response) => Some(response),
+          Err(e) => {
+            warn!("no answer from server to {:?}: {}", request, e);
+            None
+          }
+        }
+      }
+      SocketType::Server(_) => panic!("Server cannot send requests"),
+    }
+  }
+}
+
+/// what every connection's thread needs from the daemon
+#[derive(Clone)]
+struct Server {
+  clipboard: clipboard::WrappedClipboard,
+  sender: std::sync::mpsc::Sender<MPSCMessage>,
+  paste_method: WrappedPasteMethod,
+  shutdown: Shutdown,
+  /// the menu backend and the `general.menu` it was made for. locked while shown, so one menu is open
+  /// at a time
+  menu: Arc<Mutex<(menu::WrappedMenu, String)>>,
+}
+
+impl Server {
+  fn serve(&self, mut conn: LocalSocketStream) {
+    let request = match bincode::deserialize_from::<_, Request>(&mut conn) {
+      Ok(request) => request,
+      Err(e) => {
+        warn!("malformed request from client: {}", e);
+        return;
+      }
+    };
+
+    let clipboard = &self.clipboard;
+    match request {
+      Request::Toggle {
+        pid,
+        seat,
+        query,
+        type_out,
+      } => {
+        debug!("server got toggle from client pid: {} on seat {:?}", pid, seat);
+
+        let result = {
+          let mut menu = self.menu.lock().unwrap();
+          let (menu, menu_name) = &mut *menu;
+
+          // the config may have switched backends since the last toggle
+          let name = clipboard.read().unwrap().get_config().general.menu;
+          if name != *menu_name {
+            match menu::init(clipboard.clone()) {
+              Ok(new) => *menu = new,
+              Err(e) => warn!("keeping the {} menu, could not switch to {}: {:?}", menu_name, name, e),
+            }
+            *menu_name = name;
+          }
+
+          menu.show(query.as_deref().unwrap_or_default())
+        };
+
+        let (action, chosen) = match result {
+          Ok(Some((Action::Paste, chosen))) if type_out => (Action::TypeOut, chosen),
+          Ok(Some(choice)) => choice,
+          Ok(None) => return,
+          Err(_) => return,
+        };
+
+        debug!("chose {:?} on index \"{:?}\" from menu", action, chosen.index);
+        if let Err(e) = act(clipboard, &self.sender, chosen, action, seat) {
+          warn!("{}", e);
+        }
+      }
+      Request::List => {
+        let all = (0..clipboard.read().unwrap().hist.len()).collect::<Vec<_>>();
+        respond(&mut conn, Response::Entries(entries(clipboard, &all)));
+      }
+      Request::Search { query, mode } => {
+        let found = {
+          let borrow = clipboard.read().unwrap();
+          borrow.search(&query, mode.unwrap_or(borrow.get_config().search.mode))
+        };
+
+        respond(&mut conn, Response::Entries(entries(clipboard, &found)));
+      }
+      Request::Act {
+        index,
+        key,
+        action,
+        seat,
+      } => {
+        debug!("client chose {:?} on index {:?}", action, index);
+        let response = match act(clipboard, &self.sender, Chosen { index, key }, action, seat) {
+          Ok(()) => Response::Done,
+          Err(e) => {
+            warn!("{}", e);
+            Response::Error(e)
+          }
+        };
+        respond(&mut conn, response);
+      }
+      Request::Status => {
+        let status = {
+          let borrow = clipboard.read().unwrap();
+          Status {
+            menu: borrow.get_config().general.menu,
+            paste_method: *self.paste_method.read().unwrap(),
+            items: borrow.hist.len(),
+            pinned: borrow.hist.iter().filter(|item| item.pinned).count(),
+          }
+        };
+
+        respond(&mut conn, Response::Status(status));
+      }
+      Request::Clear => {
+        debug!("client asked to clear the history");
+        clipboard.write().unwrap().clear();
+        respond(&mut conn, Response::Done);
+      }
+      Request::Quit => {
+        // the daemon wakes the listener once it sees this
+        debug!("client asked the daemon to quit");
+        respond(&mut conn, Response::Done);
+        self.shutdown.request();
+      }
+    }
+  }
+}
+
+/// carry out what was chosen for an item, wherever it is in the history by now
+fn act(
+  clipboard: &clipboard::WrappedClipboard,
+  sender: &std::sync::mpsc::Sender<MPSCMessage>,
+  chosen: Chosen,
+  action: Action,
+  seat: Option<String>,
+) -> Result<(), String> {
+  let Some(index) = clipboard.read().unwrap().locate(chosen.index, &chosen.key) else {
+    return Err(format!("the item at index {:?} is no longer in the history", chosen.index));
+  };
+
+  match action {
+    Action::Paste | Action::Copy | Action::TypeOut => select(clipboard, sender, index, seat, action)?,
+    Action::Delete => clipboard.write().unwrap().remove_idx(index),
+    Action::Pin => clipboard.write().unwrap().toggle_pin_idx(index),
+    Action::Edit => {
+      // the editor can stay open for as long as the user likes, so keep serving requests meanwhile
+      let (clipboard, sender) = (clipboard.clone(), sender.clone());
+      std::thread::spawn(move || {
+        // anything copied while the editor was open is newer than the edited item
+        if let Some(key) = menu::edit(&clipboard, index) {
+          if let Err(e) = act(&clipboard, &sender, Chosen { index: 0, key }, Action::Copy, seat) {
+            warn!("{}", e);
+          }
+        }
+      });
+    }
+  }
+
+  Ok(())
+}
+
+/// hand the item at `index` to the wayland side, images included
+fn select(
+  clipboard: &clipboard::WrappedClipboard,
+  sender: &std::sync::mpsc::Sender<MPSCMessage>,
+  index: usize,
+  seat: Option<String>,
+  action: Action,
+) -> Result<(), String> {
+  let selected = {
+    let borrow = clipboard.read().unwrap();
+    (borrow.item_at(index)).and_then(|item| {
+      let text = matches!(item.data, clipboard::ItemData::Text(_));
+      Some((borrow.selection(index)?, text, item.key(), item.seat.clone()))
+    })
+  };
+  let Some(((data, mime), text, key, copied_on)) = selected else {
+    return Err(format!("nothing to copy for index {:?}", index));
+  };
+
+  // a request that does not name a seat goes back to the seat the item was copied on, so the other
+  // seats keep their clipboards
+  let seat = seat.or(copied_on);
+
+  let message = MPSCMessage {
+    data,
+    mime,
+    text,
+    key,
+    seat,
+    action,
+  };
+  sender.send(message).map_err(|_| "the clipboard watcher has stopped".to_string())?;
+  trace!("message sent to clipboard handler");
+  Ok(())
+}
+
+fn respond(conn: &mut LocalSocketStream, response: Response) {
+  if let Err(e) = bincode::serialize_into(conn, &response) {
+    warn!("failed to answer client: {}", e);
+  }
+}
+
+/// the items at the given menu indexes, in that order
+fn entries(clipboard: &clipboard::WrappedClipboard, indexes: &[usize]) -> Vec<Entry> {
+  let borrow = clipboard.read().unwrap();
+
+  (indexes.iter())
+    .filter_map(|&index| Some((index, borrow.item_at(index)?)))
+    .map(|(index, item)| Entry {
+      index,
+      key: item.key(),
+      pinned: item.pinned,
+      uses: item.uses,
+      seat: item.seat.clone(),
+      copied_at: item.copied_at,
+      content: match &item.data {
+        clipboard::ItemData::Text(text) => EntryContent::Text {
+          preview: preview(&text.text).to_string(),
+          size: text.text.len(),
+        },
+        clipboard::ItemData::Image(image) => EntryContent::Image {
+          mime: image.mime.clone(),
+          size: image.size,
+          dimensions: borrow.image_dimensions(image),
+          thumbnail: borrow.thumbnail(image),
+        },
+      },
+    })
+    .collect()
+}
+
+/// the first `PREVIEW_CHARS` characters of `text`
+fn preview(text: &str) -> &str {
+  match text.char_indices().nth(PREVIEW_CHARS) {
+    Some((end, _)) => &text[..end],
+    None => text,
+  }
 }
 
 fn get_socket_name() -> &'static str {
@@ -110,6 +484,23 @@ fn get_socket_name() -> &'static str {
   }
 }
 
+/// whether the socket file at `name` is left over from a daemon that is gone
+fn is_stale(name: &str) -> bool {
+  !name.starts_with('@') && LocalSocketStream::connect(name).is_err()
+}
+
+/// namespaced sockets disappear with the process, sockets at a path have to be removed
+fn remove_socket(name: &str) {
+  if name.starts_with('@') {
+    return;
+  }
+
+  match fs::remove_file(name) {
+    Err(e) if e.kind() != io::ErrorKind::NotFound => warn!("could not remove the socket {}: {}", name, e),
+    _ => {}
+  }
+}
+
 fn handle_error(conn: io::Result<LocalSocketStream>) -> Option<LocalSocketStream> {
   match conn {
     Ok(c) => Some(c),
@@ -119,3 +510,6 @@ fn handle_error(conn: io::Result<LocalSocketStream>) -> Option<LocalSocketStream
     }
   }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,27 @@
+use std::sync::mpsc;
+
+use super::{act, Action, Chosen};
+use crate::{
+  clipboard::{capture::Origin, fixtures},
+  config::data::Config,
+};
+
+#[test]
+fn choices_fail_once_the_watcher_is_gone() {
+  let dir = tempfile::tempdir().unwrap();
+  let (clipboard, _) = fixtures::clipboard(dir.path(), Config::default());
+  let item = fixtures::text("copied");
+  let key = item.key();
+  clipboard.write().unwrap().commit(item, &Origin::default());
+
+  let (sender, receiver) = mpsc::channel();
+  let chosen = Chosen { index: 0, key };
+  assert!(act(&clipboard, &sender, chosen.clone(), Action::Copy, None).is_ok());
+  assert_eq!(receiver.recv().unwrap().data, b"copied");
+
+  drop(receiver);
+  assert!(act(&clipboard, &sender, chosen, Action::Copy, None).is_err());
+
+  let gone = Chosen { index: 0, key: "gone".to_string() };
+  assert!(act(&clipboard, &sender, gone, Action::Copy, None).is_err());
+}
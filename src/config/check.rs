@@ -0,0 +1,348 @@
+//! finds everything wrong with a config file and says where, so a typo is reported instead of quietly
+//! resetting every setting to its default.
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+use figment::{
+  error::Kind,
+  value::{Dict, Value},
+  Figment, Metadata, Source,
+};
+use toml_edit::{DocumentMut, ImDocument, Item};
+
+use super::data::Config;
+use crate::{input::Chord, menu};
+
+/// one thing wrong with the config
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+  /// dotted path of the key, e.g. `general.maxHistory`
+  pub key: String,
+  /// 1-based line in the config file, when the key is written there
+  pub line: Option<usize>,
+  pub message: String,
+}
+
+/// a config file that cannot be used as it is
+#[derive(Debug)]
+pub struct Invalid {
+  pub path: PathBuf,
+  pub problems: Vec<Problem>,
+}
+
+impl fmt::Display for Invalid {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, problem) in self.problems.iter().enumerate() {
+      if i > 0 {
+        writeln!(f)?;
+      }
+
+      write!(f, "{}", self.path.display())?;
+      if let Some(line) = problem.line {
+        write!(f, ":{}", line)?;
+      }
+      if problem.key.is_empty() {
+        write!(f, ": {}", problem.message)?;
+      } else {
+        write!(f, ": `{}`: {}", problem.key, problem.message)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl Invalid {
+  /// a file that could not be read or written
+  pub fn io(path: &Path, error: std::io::Error) -> Self {
+    Invalid {
+      path: path.to_path_buf(),
+      problems: vec![Problem {
+        key: String::new(),
+        line: None,
+        message: error.to_string(),
+      }],
+    }
+  }
+}
+
+impl std::error::Error for Invalid {}
+
+/// the config file as written, to find the line each key is on
+pub struct Document {
+  path: PathBuf,
+  document: ImDocument<String>,
+}
+
+impl Document {
+  /// parse `raw`, reporting a syntax error with its line
+  pub fn parse(path: &Path, raw: String) -> Result<Self, Invalid> {
+    match ImDocument::parse(raw.clone()) {
+      Ok(document) => Ok(Document {
+        path: path.to_path_buf(),
+        document,
+      }),
+      Err(e) => Err(Invalid {
+        path: path.to_path_buf(),
+        problems: vec![Problem {
+          key: String::new(),
+          line: e.span().map(|span| line(&raw, span.start)),
+          message: e.message().trim().to_string(),
+        }],
+      }),
+    }
+  }
+
+  /// the file as written, to change it without losing comments
+  pub fn into_mut(self) -> DocumentMut {
+    self.document.into_mut()
+  }
+
+  /// whether `key` itself is written in the file
+  pub fn has(&self, key: &[String]) -> bool {
+    let mut item: &Item = self.document.as_item();
+    for part in key {
+      match item.as_table_like().and_then(|table| table.get(part)) {
+        Some(value) => item = value,
+        None => return false,
+      }
+    }
+
+    true
+  }
+
+  /// the line `key` is written on. when only some of its parents are, the line of the deepest one
+  pub fn line(&self, key: &[String]) -> Option<usize> {
+    let mut item: &Item = self.document.as_item();
+    let mut found = None;
+
+    for part in key {
+      let Some((key, value)) = item.as_table_like().and_then(|table| table.get_key_value(part)) else {
+        break;
+      };
+
+      found = key.span().or(found);
+      item = value;
+    }
+
+    found.map(|span| line(self.document.raw(), span.start))
+  }
+
+  /// a problem with `key`, pointing at its line when `metadata` says the value came from this file
+  fn problem(&self, key: &[String], metadata: Option<&Metadata>, message: String) -> Problem {
+    match metadata {
+      Some(metadata) if !matches!(metadata.source, Some(Source::File(_))) => Problem {
+        key: key.join("."),
+        line: None,
+        message: format!("{} (set by {})", message, metadata.name),
+      },
+      _ => Problem {
+        key: key.join("."),
+        line: self.line(key),
+        message,
+      },
+    }
+  }
+
+  /// the problems behind a failed extraction, located in this file
+  pub fn invalid(&self, error: figment::Error) -> Invalid {
+    let problems = (error.into_iter())
+      .map(|error| {
+        let mut key = error.path.clone();
+        if let Kind::UnknownField(field, _) = &error.kind {
+          if key.last() != Some(field) {
+            key.push(field.clone());
+          }
+        }
+
+        self.problem(&key, error.metadata.as_ref(), message(&error.kind))
+      })
+      .collect();
+
+    self.to_invalid(problems)
+  }
+
+  /// everything serde cannot check about a config that deserialized fine
+  pub fn check(&self, config: &Config, figment: &Figment) -> Result<(), Invalid> {
+    let problems = (rules(config).into_iter())
+      .map(|(key, message)| {
+        let metadata = figment.find_metadata(&key.join("."));
+        self.problem(&key.iter().map(|part| part.to_string()).collect::<Vec<_>>(), metadata, message)
+      })
+      .collect::<Vec<_>>();
+
+    if problems.is_empty() {
+      Ok(())
+    } else {
+      Err(self.to_invalid(problems))
+    }
+  }
+
+  /// the keys set in the file, the environment or `--set` that no setting reads. serde leaves them
+  /// out, so they are only worth a warning
+  pub fn unknown(&self, config: &Config, figment: &Figment) -> Vec<Problem> {
+    let (Ok(set), Ok(Value::Dict(_, known))) = (figment.extract::<Dict>(), Value::serialize(config)) else {
+      return vec![];
+    };
+
+    let mut found = vec![];
+    unknown_keys(&mut vec![], &set, &known, &mut found);
+
+    (found.into_iter())
+      .map(|(key, expected)| {
+        let metadata = figment.find_metadata(&key.join("."));
+        let expected = expected.iter().map(String::as_str).collect::<Vec<_>>();
+        self.problem(&key, metadata, unknown_key(key.last().unwrap(), &expected))
+      })
+      .collect()
+  }
+
+  fn to_invalid(&self, problems: Vec<Problem>) -> Invalid {
+    Invalid {
+      path: self.path.clone(),
+      problems,
+    }
+  }
+}
+
+/// collect the keys of `set` missing from `known`, each with the keys next to it that do exist
+fn unknown_keys(key: &mut Vec<String>, set: &Dict, known: &Dict, found: &mut Vec<(Vec<String>, Vec<String>)>) {
+  for (name, value) in set {
+    key.push(name.clone());
+    match (value, known.get(name)) {
+      (_, None) => found.push((key.clone(), known.keys().cloned().collect())),
+      (Value::Dict(_, set), Some(Value::Dict(_, known))) => unknown_keys(key, set, known, found),
+      // tables in arrays, like `capture.rules`
+      (Value::Array(_, set), Some(Value::Array(_, known))) => {
+        for (i, (set, known)) in set.iter().zip(known).enumerate() {
+          if let (Value::Dict(_, set), Value::Dict(_, known)) = (set, known) {
+            key.push(i.to_string());
+            unknown_keys(key, set, known, found);
+            key.pop();
+          }
+        }
+      }
+      _ => {}
+    }
+    key.pop();
+  }
+}
+
+/// values that have the right type but still make no sense
+fn rules(config: &Config) -> Vec<(Vec<&str>, String)> {
+  let mut problems = vec![];
+
+  if !menu::MENUS.contains(&config.general.menu.as_str()) {
+    problems.push((
+      vec!["general", "menu"],
+      format!(
+        "unknown menu `{}`, expected one of {}",
+        config.general.menu,
+        list(menu::MENUS)
+      ),
+    ));
+  }
+  if config.general.menu == "custom" && config.custom.command.trim().is_empty() {
+    problems.push((
+      vec!["custom", "command"],
+      "the custom menu needs a command to run".to_string(),
+    ));
+  }
+
+  if Chord::parse(&config.paste.chord).is_none() {
+    problems.push((vec!["paste", "chord"], chord_message(&config.paste.chord)));
+  }
+  for (app, chord) in &config.paste.apps {
+    if Chord::parse(chord).is_none() {
+      problems.push((vec!["paste", "apps", app], chord_message(chord)));
+    }
+  }
+
+  if config.thumbnails.size == 0 {
+    problems.push((vec!["thumbnails", "size"], "must be greater than 0".to_string()));
+  }
+
+  problems
+}
+
+fn chord_message(chord: &str) -> String {
+  format!(
+    "cannot understand the chord `{}`, write it like `ctrl+shift+v` with modifiers ctrl, shift, alt or super",
+    chord
+  )
+}
+
+/// say what is wrong in words that point at the fix
+fn message(kind: &Kind) -> String {
+  match kind {
+    Kind::UnknownField(field, expected) => unknown_key(field, expected),
+    Kind::UnknownVariant(variant, expected) => {
+      format!("unknown value `{}`, expected one of {}", variant, list(expected))
+    }
+    Kind::InvalidType(actual, expected) | Kind::InvalidValue(actual, expected) => {
+      format!("expected {}, found {}", expected, actual)
+    }
+    kind => kind.to_string(),
+  }
+}
+
+fn unknown_key(field: &str, expected: &[&str]) -> String {
+  match suggest(field, expected) {
+    Some(suggestion) => format!("unknown key, did you mean `{}`?", suggestion),
+    None if expected.is_empty() => "unknown key".to_string(),
+    None => format!("unknown key, expected one of {}", list(expected)),
+  }
+}
+
+/// the expected key `field` was most likely meant to be
+pub fn suggest<'a>(field: &str, expected: &[&'a str]) -> Option<&'a str> {
+  // grab_focus or GrabFocus for grabFocus
+  let normalize = |key: &str| key.replace(['_', '-'], "").to_lowercase();
+  if let Some(key) = expected.iter().find(|key| normalize(key) == normalize(field)) {
+    return Some(key);
+  }
+
+  (expected.iter())
+    .map(|key| (key, distance(key, field)))
+    .filter(|(_, distance)| *distance <= 2)
+    .min_by_key(|(_, distance)| *distance)
+    .map(|(key, _)| *key)
+}
+
+/// levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+  let b = b.chars().collect::<Vec<_>>();
+  let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+  for (i, a) in a.chars().enumerate() {
+    let mut diagonal = row[0];
+    row[0] = i + 1;
+
+    for (j, b) in b.iter().enumerate() {
+      let substitution = diagonal + usize::from(a != *b);
+      diagonal = row[j + 1];
+      row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+    }
+  }
+
+  row[b.len()]
+}
+
+fn list(items: &[&str]) -> String {
+  items
+    .iter()
+    .map(|item| format!("`{}`", item))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// 1-based line of a byte offset
+fn line(raw: &str, offset: usize) -> usize {
+  raw[..offset.min(raw.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,121 @@
+use std::fs;
+
+use super::{distance, suggest, Invalid, Problem};
+use crate::config::{consts, data::Config, overrides::Overrides};
+
+fn load(contents: &str) -> Result<Config, Invalid> {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("config.toml");
+  fs::write(&path, contents).unwrap();
+
+  Config::from_path(&path, &Overrides::default())
+}
+
+fn problems(contents: &str) -> Vec<Problem> {
+  load(contents).unwrap_err().problems
+}
+
+#[test]
+fn missing_keys_take_the_documented_defaults() {
+  let config = load("[bemenu]\nfont = \"sans 10\"\n\n[fuzzel]\nprompt = \"> \"\n").unwrap();
+
+  assert_eq!(config.bemenu.font, "sans 10");
+  assert_eq!(config.bemenu.lines, consts::LINES);
+  assert_eq!(config.bemenu.grab_focus, consts::GRAB_FOCUS);
+  assert_eq!(config.fuzzel.lines, consts::LINES);
+  assert_eq!(config.paste.chord, consts::PASTE_CHORD);
+}
+
+#[test]
+fn unknown_keys_are_located_and_corrected() {
+  let config = load("[general]\nmenu = \"fuzzel\"\n\n[bemenu]\ngrab_focus = false\nlines = 3\n").unwrap();
+
+  assert_eq!(config.general.menu, "fuzzel");
+  assert_eq!(config.bemenu.lines, 3);
+  assert_eq!(
+    config.unknown().unwrap().problems,
+    vec![Problem {
+      key: "bemenu.grab_focus".to_string(),
+      line: Some(5),
+      message: "unknown key, did you mean `grabFocus`?".to_string(),
+    }]
+  );
+}
+
+#[test]
+fn unknown_keys_are_found_in_tables_and_arrays() {
+  let config = load(
+    "[paste.apps]\nfoot = \"ctrl+shift+v\"\n\n[[capture.rules]]\nnmae = \"secrets\"\naction = \"ignore\"\n\n[colours]\nx = 1\n",
+  )
+  .unwrap();
+  let keys = (config.unknown().unwrap().problems.into_iter())
+    .map(|p| (p.key, p.message))
+    .collect::<Vec<_>>();
+
+  assert_eq!(
+    keys,
+    vec![
+      (
+        "capture.rules.0.nmae".to_string(),
+        "unknown key, did you mean `name`?".to_string()
+      ),
+      ("colours".to_string(), keys[1].1.clone()),
+    ]
+  );
+  assert_eq!(config.capture.rules.len(), 1);
+  assert!(load("[general]\nmenu = \"fuzzel\"\n").unwrap().unknown().is_none());
+}
+
+#[test]
+fn wrong_types_are_located() {
+  let problems = problems("[general]\n\nmaxHistory = \"lots\"\n");
+
+  assert_eq!(problems.len(), 1);
+  assert_eq!(problems[0].key, "general.maxHistory");
+  assert_eq!(problems[0].line, Some(3));
+  assert!(
+    problems[0].message.contains("found string \"lots\""),
+    "{}",
+    problems[0].message
+  );
+}
+
+#[test]
+fn syntax_errors_are_located() {
+  let problems = problems("[general]\nmenu = \"bemenu\"\nlines = \n");
+
+  assert_eq!(problems.len(), 1);
+  assert_eq!(problems[0].line, Some(3));
+}
+
+#[test]
+fn nonsense_values_are_reported() {
+  let invalid = load("[general]\nmenu = \"bmenu\"\n\n[paste]\nchord = \"ctrl+nope\"\n").unwrap_err();
+  let keys = invalid
+    .problems
+    .iter()
+    .map(|p| (p.key.as_str(), p.line))
+    .collect::<Vec<_>>();
+
+  assert_eq!(keys, vec![("general.menu", Some(2)), ("paste.chord", Some(5))]);
+  assert!(invalid
+    .to_string()
+    .contains("config.toml:2: `general.menu`: unknown menu `bmenu`"));
+}
+
+#[test]
+fn suggests_close_keys_only() {
+  let expected = ["maxHistory", "menu", "allowImages"];
+
+  assert_eq!(suggest("maxHistroy", &expected), Some("maxHistory"));
+  assert_eq!(suggest("allow-images", &expected), Some("allowImages"));
+  assert_eq!(suggest("colour", &expected), None);
+  assert_eq!(distance("kitten", "sitting"), 3);
+}
+
+#[test]
+fn the_bundled_config_is_valid() {
+  let bundled = crate::config::resources::Resource::get(consts::CONFIG_FILE).unwrap();
+
+  load(std::str::from_utf8(&bundled.data).unwrap()).unwrap();
+}
@@ -1,10 +1,30 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
+use super::overrides::{parse_setting, Overrides};
+use crate::search::SearchMode;
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
   #[command(subcommand)]
   pub command: Option<Commands>,
+  /// read this config file instead of ~/.config/wayclip/config.toml
+  #[arg(long, global = true, value_name = "PATH")]
+  pub config: Option<PathBuf>,
+  /// override a config key for this run, e.g. `--set general.maxHistory=100` (repeatable)
+  #[arg(long, global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+  pub set: Vec<(String, String)>,
+}
+
+impl Cli {
+  pub fn overrides(&self) -> Overrides {
+    Overrides {
+      config: self.config.clone(),
+      set: self.set.clone(),
+    }
+  }
 }
 
 #[derive(Subcommand)]
@@ -12,7 +32,42 @@ pub enum Commands {
   /// [DEFAULT]; starts the clipboard monitor
   Start,
   /// activates the clipboard menu dropdown (for use in a keybinding)
-  Toggle,
+  Toggle {
+    /// name of the seat to paste on (defaults to every seat)
+    #[arg(long)]
+    seat: Option<String>,
+    /// only show history items matching this
+    #[arg(long)]
+    query: Option<String>,
+    /// type the chosen item out key by key instead of pasting it
+    #[arg(long)]
+    type_out: bool,
+  },
+  /// prints the history items matching a query, best match first
+  Search {
+    query: String,
+    /// how to match (defaults to the `search.mode` config option)
+    #[arg(long, value_enum)]
+    mode: Option<SearchMode>,
+    /// print at most this many items
+    #[arg(long)]
+    limit: Option<usize>,
+  },
+  /// opens a picker for the clipboard history in the terminal
+  Pick {
+    /// name of the seat to paste on (defaults to every seat)
+    #[arg(long)]
+    seat: Option<String>,
+  },
+  /// shows what the running daemon is doing, like how it pastes
+  Status,
+  /// saves the history and stops the running daemon
+  Quit,
+  /// works with the config file
+  Config {
+    #[command(subcommand)]
+    command: ConfigCommands,
+  },
   /// dumps the clipboard contents to stdout
   Dump,
   /// empties the clipboard
@@ -20,3 +75,25 @@ pub enum Commands {
   /// install
   Install,
 }
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+  /// reports every problem with the config file and where it is, exiting 1 if there are any
+  Check,
+  /// prints where the config file is
+  Path,
+  /// prints the value a key ends up with, e.g. `bemenu.grabFocus`
+  Get { key: String },
+  /// writes a key to the config file, keeping its comments
+  Set {
+    key: String,
+    /// read as toml, or as a string when it is not valid toml
+    value: String,
+  },
+  /// prints the config file
+  Show {
+    /// print every value wayclip uses instead, with where it came from
+    #[arg(long)]
+    effective: bool,
+  },
+}
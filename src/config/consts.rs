@@ -1,3 +1,5 @@
+use crate::menu::Action;
+
 pub const APP_NAME: &str = "wayclip";
 pub const CONFIG_FILE: &str = "config.toml";
 pub const SYSTEMD_FILE: &str = "wayclip.service";
@@ -11,10 +13,50 @@ pub const ALLOW_IMAGES: bool = true;
 // [data]
 pub const MIME: &str = "text/plain";
 pub const DEDUPE: bool = true;
+pub const BLOB_THRESHOLD: usize = 64 * 1024;
+pub const READ_TIMEOUT: u64 = 2000;
+pub const MAX_READ_SIZE: usize = 64 * 1024 * 1024;
 
 // [encryption]
 pub const ENCRYPT: bool = true;
 
+// [preview]
+pub const PREVIEW_WIDTH: usize = 80;
+pub const NEWLINE_MARKER: &str = "⏎";
+
+// [thumbnails]
+pub const THUMBNAILS: bool = true;
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+// [actions]
+pub const CUSTOM_ACTIONS: [Action; 5] = [Action::Copy, Action::Delete, Action::Pin, Action::Edit, Action::TypeOut];
+pub const EDITOR: &str = "${TERMINAL:-foot} -e ${EDITOR:-vi} {file}";
+
+// [paste]
+pub const PASTE_CHORD: &str = "ctrl+v";
+pub const TERMINAL_CHORD: &str = "ctrl+shift+v";
+pub const AUTO_PASTE: bool = true;
+pub const PASTE_DELAY: u64 = 50;
+pub const CONFIRM_TIMEOUT: u64 = 500;
+/// app ids of terminals, which take ctrl+v as a key for the running program
+pub const TERMINALS: &[&str] = &[
+  "foot",
+  "footclient",
+  "kitty",
+  "Alacritty",
+  "org.wezfurlong.wezterm",
+  "com.mitchellh.ghostty",
+  "org.gnome.Terminal",
+  "org.gnome.Console",
+  "org.kde.konsole",
+  "xterm",
+];
+
+// [capture]
+pub const PASSWORD_MANAGERS: &str = "password managers";
+/// offered by KeePassXC and other password managers alongside secrets
+pub const PASSWORD_HINT: &str = "x-kde-passwordManagerHint";
+
 // [bemenu]
 pub const FONT: &str = "monospace 12";
 pub const TITLE: &str = "search >";
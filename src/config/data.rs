@@ -1,22 +1,41 @@
-use std::path::PathBuf;
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+};
 
-use super::{consts::*, file::FileHelper};
+use super::{
+  check::{Document, Invalid, Problem},
+  consts::*,
+  file::FileHelper,
+  overrides::Overrides,
+};
+use crate::{clipboard::capture::Capture, menu::Action, search::SearchMode};
 use figment::{
   providers::{Format, Toml},
   Figment,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Deserialize, Debug, Default)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
 pub struct Config {
-  #[serde(default)]
   pub general: General,
-  #[serde(default)]
   pub data: Data,
-  #[serde(default)]
   pub encryption: Encryption,
-  #[serde(default)]
+  pub search: Search,
+  pub preview: PreviewConfig,
+  pub thumbnails: Thumbnails,
+  pub actions: Actions,
+  pub paste: Paste,
+  pub capture: CaptureConfig,
   pub bemenu: BeMenuConfig,
+  pub fuzzel: LauncherConfig,
+  pub wofi: LauncherConfig,
+  pub rofi: LauncherConfig,
+  pub tofi: LauncherConfig,
+  pub dmenu: LauncherConfig,
+  pub custom: CustomMenuConfig,
 
   // private
   #[serde(skip)]
@@ -25,32 +44,88 @@ pub struct Config {
   helper: FileHelper,
   #[serde(skip)]
   path: PathBuf,
+  #[serde(skip)]
+  unknown: Vec<Problem>,
 }
 
 impl Config {
+  /// read the config file, logging what is wrong with it and using the defaults when it is invalid
   pub fn load(helper: FileHelper) -> Self {
-    let path = helper.init_config();
-    let figment = Figment::new().join(Toml::file(path.clone()));
-    let config = figment.extract::<Config>();
+    let config = Self::try_load(helper.clone()).unwrap_or_else(|e| {
+      tracing::error!("{}", e);
+      tracing::error!("the config is invalid, using the defaults (see `wayclip config check`)");
 
-    let mut config = config.unwrap_or_default();
-    config.figment = figment;
-    config.helper = helper;
-    config.path = path;
+      Config {
+        helper: helper.clone(),
+        path: helper.init_config(),
+        ..Config::default()
+      }
+    });
+    config.warn_unknown();
 
     config
   }
+
+  /// read the config file, failing instead of falling back to defaults
+  pub fn try_load(helper: FileHelper) -> Result<Self, Invalid> {
+    let mut config = Self::from_path(&helper.init_config(), &helper.overrides)?;
+    config.helper = helper;
+
+    Ok(config)
+  }
+
+  /// read and check the config at `path`, with environment variables and `overrides` on top
+  pub fn from_path(path: &Path, overrides: &Overrides) -> Result<Self, Invalid> {
+    let raw = fs::read_to_string(path).map_err(|e| Invalid::io(path, e))?;
+    let document = Document::parse(path, raw)?;
+
+    let figment = (Figment::new())
+      .join(Toml::file(path))
+      .merge(Overrides::env())
+      .merge(overrides.clone());
+    let mut config = figment.extract::<Config>().map_err(|e| document.invalid(e))?;
+    document.check(&config, &figment)?;
+
+    config.unknown = document.unknown(&config, &figment);
+    config.figment = figment;
+    config.path = path.to_path_buf();
+
+    Ok(config)
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// where each value was read from
+  pub fn figment(&self) -> &Figment {
+    &self.figment
+  }
+
+  /// the keys no setting reads, which were left out while everything else was used
+  pub fn unknown(&self) -> Option<Invalid> {
+    (!self.unknown.is_empty()).then(|| Invalid {
+      path: self.path.clone(),
+      problems: self.unknown.clone(),
+    })
+  }
+
+  /// log the keys that were left out, so a typo does not go unnoticed
+  pub fn warn_unknown(&self) {
+    if let Some(unknown) = self.unknown() {
+      tracing::warn!("{}", unknown);
+      tracing::warn!("ignoring the keys above (see `wayclip config check`)");
+    }
+  }
 }
 
-#[derive(Clone, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
 pub struct General {
-  #[serde(default)]
   pub max_history: usize,
-  #[serde(default)]
   pub menu: String,
-  #[serde(default)]
   pub allow_images: bool,
+  pub seats: Vec<String>,
 }
 
 impl Default for General {
@@ -59,16 +134,19 @@ impl Default for General {
       max_history: MAX_HISTORY,
       menu: MENU.to_string(),
       allow_images: ALLOW_IMAGES,
+      seats: vec![],
     }
   }
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
 pub struct Data {
-  #[serde(default)]
   pub mime: String,
-  #[serde(default)]
   pub dedupe: bool,
+  pub blob_threshold: usize,
+  pub read_timeout: u64,
+  pub max_read_size: usize,
 }
 
 impl Default for Data {
@@ -76,15 +154,17 @@ impl Default for Data {
     Data {
       mime: MIME.to_string(),
       dedupe: DEDUPE,
+      blob_threshold: BLOB_THRESHOLD,
+      read_timeout: READ_TIMEOUT,
+      max_read_size: MAX_READ_SIZE,
     }
   }
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
 pub struct Encryption {
-  #[serde(default)]
   pub encrypt: bool,
-  #[serde(default)]
   pub key: Option<String>,
 }
 
@@ -97,18 +177,143 @@ impl Default for Encryption {
   }
 }
 
-#[derive(Clone, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct Search {
+  pub mode: SearchMode,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct PreviewConfig {
+  pub width: usize,
+  pub newline: String,
+}
+
+impl Default for PreviewConfig {
+  fn default() -> Self {
+    PreviewConfig {
+      width: PREVIEW_WIDTH,
+      newline: NEWLINE_MARKER.to_string(),
+    }
+  }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Thumbnails {
+  pub enabled: bool,
+  pub size: u32,
+}
+
+impl Default for Thumbnails {
+  fn default() -> Self {
+    Thumbnails {
+      enabled: THUMBNAILS,
+      size: THUMBNAIL_SIZE,
+    }
+  }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Actions {
+  /// actions bound to the custom keys of the menu, the first one to custom key 1
+  pub custom: Vec<Action>,
+  pub editor: String,
+}
+
+impl Default for Actions {
+  fn default() -> Self {
+    Actions {
+      custom: CUSTOM_ACTIONS.to_vec(),
+      editor: EDITOR.to_string(),
+    }
+  }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Paste {
+  /// keys pressed to paste, e.g. `ctrl+v`
+  pub chord: String,
+  /// chords for particular applications, by app id
+  pub apps: HashMap<String, String>,
+  /// paste after copying, or only copy
+  pub auto: bool,
+  /// milliseconds to wait before pasting
+  pub delay: u64,
+  /// longest to wait in milliseconds for the compositor to confirm the copy
+  pub confirm_timeout: u64,
+}
+
+impl Paste {
+  /// the chord that pastes into the application `app_id`
+  pub fn chord(&self, app_id: Option<&str>) -> String {
+    let app = app_id.and_then(|app_id| (self.apps.iter()).find(|(app, _)| app.eq_ignore_ascii_case(app_id)));
+
+    match app {
+      Some((_, chord)) => chord.clone(),
+      None => self.chord.clone(),
+    }
+  }
+}
+
+impl Default for Paste {
+  fn default() -> Self {
+    Paste {
+      chord: PASTE_CHORD.to_string(),
+      apps: (TERMINALS.iter())
+        .map(|app| (app.to_string(), TERMINAL_CHORD.to_string()))
+        .collect(),
+      auto: AUTO_PASTE,
+      delay: PASTE_DELAY,
+      confirm_timeout: CONFIRM_TIMEOUT,
+    }
+  }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct CaptureConfig {
+  /// checked in order, the first one matching a selection decides what happens to it
+  pub rules: Vec<CaptureRule>,
+}
+
+impl Default for CaptureConfig {
+  fn default() -> Self {
+    CaptureConfig {
+      rules: vec![CaptureRule {
+        name: Some(PASSWORD_MANAGERS.to_string()),
+        app: None,
+        mime: Some(PASSWORD_HINT.to_string()),
+        action: Capture::Ignore,
+      }],
+    }
+  }
+}
+
+/// a rule for selections from some application, or with some mime type. a rule with neither
+/// matches every selection
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CaptureRule {
+  /// recorded on the items the rule matched
+  pub name: Option<String>,
+  /// app id of the focused window, ignoring case
+  pub app: Option<String>,
+  /// a mime type the selection must be offered as, ignoring case
+  pub mime: Option<String>,
+  pub action: Capture,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
 pub struct BeMenuConfig {
-  #[serde(default)]
   pub font: String,
-  #[serde(default)]
   pub title: String,
-  #[serde(default)]
   pub lines: u32,
-  #[serde(default)]
   pub grab_focus: bool,
-  #[serde(default)]
   pub monitor: i32,
 }
 
@@ -123,3 +328,58 @@ impl Default for BeMenuConfig {
     }
   }
 }
+
+/// settings shared by the dmenu-style launchers (fuzzel, wofi, rofi, tofi and dmenu)
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LauncherConfig {
+  /// binary to run instead of the launcher's own name
+  pub command: Option<String>,
+  /// extra arguments passed after the ones wayclip sets
+  pub args: Vec<String>,
+  pub prompt: String,
+  pub lines: u32,
+}
+
+impl Default for LauncherConfig {
+  fn default() -> Self {
+    LauncherConfig {
+      command: None,
+      args: vec![],
+      prompt: TITLE.to_string(),
+      lines: LINES,
+    }
+  }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomMenuFormat {
+  /// `<index>\t<preview>` per line
+  #[default]
+  Tsv,
+  /// `{"index": <index>, "preview": "<preview>"}` per line
+  Json,
+}
+
+/// a menu command wayclip knows nothing about
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CustomMenuConfig {
+  /// run with sh -c, with `{prompt}` and `{lines}` filled in
+  pub command: String,
+  pub format: CustomMenuFormat,
+  pub prompt: String,
+  pub lines: u32,
+}
+
+impl Default for CustomMenuConfig {
+  fn default() -> Self {
+    CustomMenuConfig {
+      command: String::new(),
+      format: CustomMenuFormat::default(),
+      prompt: TITLE.to_string(),
+      lines: LINES,
+    }
+  }
+}
@@ -0,0 +1,246 @@
+//! reads and writes single keys of the config, so nobody has to guess at names like `grabFocus`
+//! versus `grab_focus`. the file is edited in place, keeping its comments and layout.
+
+use std::{fs, path::Path};
+
+use figment::{
+  value::{Dict, Num, Value},
+  Source,
+};
+use toml_edit::{Array, Item, Key, Table};
+
+use super::{
+  check::{suggest, Document, Invalid, Problem},
+  data::Config,
+  overrides::Overrides,
+};
+
+/// one value of the merged config
+#[derive(Clone, Debug, PartialEq)]
+pub struct Setting {
+  /// dotted path of the table holding the key, empty at the top level
+  pub table: String,
+  /// the key within its table, quoted when toml needs it to be
+  pub name: String,
+  /// the value as it would be written in config.toml
+  pub value: String,
+  /// where the value came from: `file:line`, an override, or `default`
+  pub source: String,
+}
+
+/// every value the config resolves to, with where it came from
+pub fn effective(config: &Config) -> Vec<Setting> {
+  let document = fs::read_to_string(config.path())
+    .ok()
+    .and_then(|raw| Document::parse(config.path(), raw).ok());
+
+  let mut settings = vec![];
+  if let Ok(Value::Dict(_, dict)) = Value::serialize(config) {
+    collect(config, document.as_ref(), &mut vec![], &dict, &mut settings);
+  }
+
+  settings
+}
+
+fn collect(config: &Config, document: Option<&Document>, path: &mut Vec<String>, dict: &Dict, out: &mut Vec<Setting>) {
+  // plain values first, so they stay under their table's header
+  let (tables, values): (Vec<_>, Vec<_>) = dict.iter().partition(|(_, value)| matches!(value, Value::Dict(..)));
+
+  for (key, value) in values {
+    let Some(rendered) = render(value) else {
+      continue;
+    };
+
+    path.push(key.clone());
+    out.push(Setting {
+      table: header(&path[..path.len() - 1]),
+      name: Key::new(key.as_str()).to_string(),
+      value: rendered.to_string(),
+      source: source(config, document, path),
+    });
+    path.pop();
+  }
+
+  for (key, value) in tables {
+    if let Value::Dict(_, dict) = value {
+      path.push(key.clone());
+      collect(config, document, path, dict, out);
+      path.pop();
+    }
+  }
+}
+
+/// where the figment says `key` came from. keys it cannot find were left out of every source, unless
+/// they are written in the file with dots figment reads as nesting, like `org.gnome.Terminal`
+fn source(config: &Config, document: Option<&Document>, key: &[String]) -> String {
+  let line = document.and_then(|document| document.has(key).then(|| document.line(key)).flatten());
+
+  match (config.figment().find_metadata(&key.join(".")), line) {
+    (Some(metadata), _) if !matches!(metadata.source, Some(Source::File(_))) => metadata.name.to_string(),
+    (_, Some(line)) => format!("{}:{}", config.path().display(), line),
+    (Some(_), None) => config.path().display().to_string(),
+    (None, None) => "default".to_string(),
+  }
+}
+
+fn header(path: &[String]) -> String {
+  (path.iter())
+    .map(|part| Key::new(part.as_str()).to_string())
+    .collect::<Vec<_>>()
+    .join(".")
+}
+
+/// the effective value of `key`, a whole table printing as its `key = value` lines
+pub fn get(config: &Config, key: &str) -> Result<String, String> {
+  let Ok(config_value) = Value::serialize(config) else {
+    return Err("cannot read the config".to_string());
+  };
+
+  match config_value.clone().find(key) {
+    Some(Value::Dict(_, dict)) => Ok(
+      (dict.iter())
+        .filter_map(|(name, value)| Some(format!("{} = {}", Key::new(name.as_str()), render(value)?)))
+        .collect::<Vec<_>>()
+        .join("\n"),
+    ),
+    Some(value) => render(&value)
+      .map(|value| value.to_string())
+      .ok_or_else(|| format!("`{}` is not set", key)),
+    None => {
+      let (parent, name) = key.rsplit_once('.').unwrap_or(("", key));
+      let siblings = if parent.is_empty() {
+        Some(config_value)
+      } else {
+        config_value.find(parent)
+      };
+      let names = match &siblings {
+        Some(Value::Dict(_, dict)) => dict.keys().map(String::as_str).collect::<Vec<_>>(),
+        _ => vec![],
+      };
+
+      match suggest(name, &names) {
+        Some(suggestion) if parent.is_empty() => Err(format!("unknown key `{}`, did you mean `{}`?", key, suggestion)),
+        Some(suggestion) => Err(format!("unknown key `{}`, did you mean `{}.{}`?", key, parent, suggestion)),
+        None => Err(format!("unknown key `{}`", key)),
+      }
+    }
+  }
+}
+
+/// write `value` to `key` in the file at `path`, keeping its comments. `value` is read as toml, and
+/// as a plain string when it is not valid toml, so `fuzzel` needs no quotes. the file is only
+/// replaced when the result is a valid config
+pub fn set(path: &Path, key: &str, value: &str) -> Result<(), Invalid> {
+  let raw = fs::read_to_string(path).map_err(|e| Invalid::io(path, e))?;
+  let mut document = Document::parse(path, raw)?.into_mut();
+
+  let not_a_table = |part: &str| Invalid {
+    path: path.to_path_buf(),
+    problems: vec![Problem {
+      key: key.to_string(),
+      line: None,
+      message: format!("`{}` is not a table", part),
+    }],
+  };
+  // parsed like a toml key, so names with dots can be quoted: `paste.apps."org.gnome.Terminal"`
+  let parts = match Key::parse(key) {
+    Ok(parts) => parts.iter().map(|part| part.get().to_string()).collect::<Vec<_>>(),
+    Err(_) => {
+      return Err(Invalid {
+        path: path.to_path_buf(),
+        problems: vec![Problem {
+          key: key.to_string(),
+          line: None,
+          message: "keys are written like `general.maxHistory`, with quotes around names that have dots in them"
+            .to_string(),
+        }],
+      })
+    }
+  };
+
+  let (name, tables) = parts.split_last().unwrap();
+  let mut item = document.as_item_mut();
+  for part in tables {
+    let table = item.as_table_like_mut().ok_or_else(|| not_a_table(part))?;
+    if table.get(part).is_none() {
+      table.insert(part, Item::Table(Table::new()));
+    }
+    item = table.get_mut(part).unwrap();
+  }
+
+  let table = item.as_table_like_mut().ok_or_else(|| not_a_table(tables.last().unwrap_or(name)))?;
+  let mut value = value.trim().parse::<toml_edit::Value>().unwrap_or_else(|_| value.trim().into());
+  match table.get_mut(name) {
+    // replacing only the value keeps the comments around it
+    Some(existing) => {
+      if let Some(old) = existing.as_value() {
+        *value.decor_mut() = old.decor().clone();
+      }
+      *existing = Item::Value(value);
+    }
+    None => {
+      table.insert(name, Item::Value(value));
+    }
+  }
+
+  // check the new file before it replaces the old one, then swap them in one step. a symlinked config
+  // is replaced where it points, so the link stays
+  let target = fs::canonicalize(path).map_err(|e| Invalid::io(path, e))?;
+  let name = target.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+  let staged = target.with_file_name(format!(".{}.new", name));
+  fs::write(&staged, document.to_string()).map_err(|e| Invalid::io(path, e))?;
+
+  let checked = Config::from_path(&staged, &Overrides::default()).and_then(|config| {
+    // other unknown keys in the file are left alone, but a new one is most likely a typo
+    let key = parts.join(".");
+    let typo = (config.unknown().into_iter())
+      .flat_map(|unknown| unknown.problems)
+      .find(|problem| key == problem.key || key.starts_with(&format!("{}.", problem.key)));
+
+    match typo {
+      Some(problem) => Err(Invalid {
+        path: staged.clone(),
+        problems: vec![problem],
+      }),
+      None => Ok(()),
+    }
+  });
+  if let Err(mut invalid) = checked {
+    let _ = fs::remove_file(&staged);
+    invalid.path = path.to_path_buf();
+    return Err(invalid);
+  }
+
+  fs::rename(&staged, &target).map_err(|e| Invalid::io(path, e))
+}
+
+/// a figment value written as toml, nothing for values that are not set
+fn render(value: &Value) -> Option<toml_edit::Value> {
+  match value {
+    Value::String(_, string) => Some(string.as_str().into()),
+    Value::Char(_, char) => Some(char.to_string().into()),
+    Value::Bool(_, bool) => Some((*bool).into()),
+    Value::Num(_, num) => Some(number(*num)),
+    Value::Empty(..) => None,
+    Value::Dict(_, dict) => Some(toml_edit::Value::InlineTable(
+      (dict.iter())
+        .filter_map(|(key, value)| Some((key.clone(), render(value)?)))
+        .collect(),
+    )),
+    Value::Array(_, values) => Some(toml_edit::Value::Array(values.iter().filter_map(render).collect::<Array>())),
+  }
+}
+
+fn number(num: Num) -> toml_edit::Value {
+  let int = (num.to_i128())
+    .or_else(|| num.to_u128().and_then(|int| i128::try_from(int).ok()))
+    .and_then(|int| i64::try_from(int).ok());
+
+  match int {
+    Some(int) => int.into(),
+    None => num.to_f64().unwrap_or_default().into(),
+  }
+}
+
+#[cfg(test)]
+mod tests;
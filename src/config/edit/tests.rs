@@ -0,0 +1,120 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use super::{effective, get, set};
+use crate::config::{data::Config, overrides::Overrides};
+
+const CONFIG: &str = "# wayclip config
+[general]
+menu = 'wofi' # which menu to show
+
+[bemenu]
+# shown before the query
+title = 'clip >'
+";
+
+fn file(dir: &tempfile::TempDir) -> PathBuf {
+  let path = dir.path().join("config.toml");
+  fs::write(&path, CONFIG).unwrap();
+  path
+}
+
+fn load(path: &Path, set: &[(&str, &str)]) -> Config {
+  let overrides = Overrides {
+    config: None,
+    set: set.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+  };
+
+  Config::from_path(path, &overrides).unwrap()
+}
+
+#[test]
+fn setting_keeps_comments_and_layout() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = file(&dir);
+
+  set(&path, "general.menu", "fuzzel").unwrap();
+  set(&path, "bemenu.lines", "20").unwrap();
+  set(&path, "paste.auto", "false").unwrap();
+
+  let written = fs::read_to_string(&path).unwrap();
+  assert!(written.starts_with("# wayclip config\n[general]\nmenu = \"fuzzel\" # which menu to show\n"), "{}", written);
+  assert!(written.contains("# shown before the query\ntitle = 'clip >'\nlines = 20\n"), "{}", written);
+  assert!(written.contains("[paste]\nauto = false\n"), "{}", written);
+
+  let config = load(&path, &[]);
+  assert_eq!(config.general.menu, "fuzzel");
+  assert_eq!(config.bemenu.lines, 20);
+  assert!(!config.paste.auto);
+}
+
+#[test]
+fn setting_takes_quoted_keys_and_follows_symlinks() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = file(&dir);
+  let link = dir.path().join("link.toml");
+  std::os::unix::fs::symlink(&path, &link).unwrap();
+
+  set(&link, "paste.apps.\"org.gnome.Terminal\"", "ctrl+shift+v").unwrap();
+
+  assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+  assert_eq!(load(&path, &[]).paste.apps["org.gnome.Terminal"], "ctrl+shift+v");
+  assert!(set(&link, "paste..chord", "ctrl+v").is_err());
+  assert!(set(&link, "", "ctrl+v").is_err());
+}
+
+#[test]
+fn setting_refuses_invalid_configs() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = file(&dir);
+
+  let invalid = set(&path, "bemenu.grab_focus", "false").unwrap_err();
+  assert_eq!(invalid.path, path);
+  assert!(invalid.problems[0].message.contains("grabFocus"));
+
+  assert!(set(&path, "bemenu.lines", "many").is_err());
+  assert!(set(&path, "general.menu.name", "x").is_err());
+  assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG);
+  assert!(!dir.path().join(".config.toml.new").exists());
+
+  // an unknown key already in the file does not block other changes
+  fs::write(&path, "[colours]\nx = 1\n").unwrap();
+  set(&path, "bemenu.lines", "3").unwrap();
+  assert!(set(&path, "colours.y", "2").is_err());
+}
+
+#[test]
+fn getting_resolves_defaults_and_suggests_keys() {
+  let dir = tempfile::tempdir().unwrap();
+  let config = load(&file(&dir), &[]);
+
+  assert_eq!(get(&config, "general.menu").unwrap(), "\"wofi\"");
+  assert_eq!(get(&config, "bemenu.lines").unwrap(), "15");
+  assert!(get(&config, "bemenu").unwrap().contains("title = \"clip >\""));
+  assert_eq!(
+    get(&config, "bemenu.grab_focus").unwrap_err(),
+    "unknown key `bemenu.grab_focus`, did you mean `bemenu.grabFocus`?"
+  );
+  assert!(get(&config, "encryption.key").is_err());
+}
+
+#[test]
+fn effective_values_name_their_source() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = file(&dir);
+  let settings = effective(&load(&path, &[("bemenu.lines", "3")]));
+  let find = |table: &str, name: &str| {
+    (settings.iter())
+      .find(|setting| setting.table == table && setting.name == name)
+      .unwrap_or_else(|| panic!("no {}.{}", table, name))
+  };
+
+  assert_eq!(find("general", "menu").source, format!("{}:3", path.display()));
+  assert_eq!(find("bemenu", "title").source, format!("{}:7", path.display()));
+  assert_eq!(find("bemenu", "lines").value, "3");
+  assert_eq!(find("bemenu", "lines").source, "`--set`");
+  assert_eq!(find("general", "maxHistory").source, "default");
+  assert_eq!(find("paste.apps", "\"org.gnome.Terminal\"").value, "\"ctrl+shift+v\"");
+}
@@ -1,5 +1,6 @@
 #[derive(Debug)]
 pub enum Error {
   BMenu(String),
-  NoMenu,
+  Launcher(String),
+  NoMenu(String),
 }
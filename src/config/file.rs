@@ -1,14 +1,19 @@
 use cocoon::MiniCocoon;
-use std::{fmt, fs, path::PathBuf};
+use std::{collections::HashSet, fmt, fs, path::PathBuf, sync::Arc};
+use tracing::error;
 
-use crate::clipboard::Item;
+use crate::clipboard::{Item, LegacyItem};
 
 use super::{
   consts::{APP_NAME, CONFIG_FILE, SYSTEMD_FILE},
   data::Config,
+  overrides::Overrides,
   resources::Resource,
 };
 
+#[cfg(test)]
+mod tests;
+
 pub fn generate_cocoon(seed: [u8; 32], key: Option<String>) -> MiniCocoon {
   let key = match key {
     Some(key) => {
@@ -30,9 +35,14 @@ pub struct FileHelper {
   pub cache_dir: PathBuf,
   pub log_dir: PathBuf,
   pub systemd_dir: PathBuf,
+  /// for files that must not outlive the session, like thumbnails of encrypted images. unset without
+  /// `$XDG_RUNTIME_DIR`, as anywhere else they would be left on disk unencrypted
+  pub runtime_dir: Option<PathBuf>,
+  /// from the command line, applied every time the config is read
+  pub overrides: Overrides,
 
   // privates
-  cocoon: Option<MiniCocoon>,
+  cocoon: Option<Arc<MiniCocoon>>,
 }
 
 impl Clone for FileHelper {
@@ -42,9 +52,11 @@ impl Clone for FileHelper {
       cache_dir: self.cache_dir.clone(),
       log_dir: self.log_dir.clone(),
       systemd_dir: self.systemd_dir.clone(),
+      runtime_dir: self.runtime_dir.clone(),
+      overrides: self.overrides.clone(),
 
       // privates
-      cocoon: None,
+      cocoon: self.cocoon.clone(),
     }
   }
 }
@@ -55,29 +67,56 @@ impl fmt::Debug for FileHelper {
       .field("config_dir", &self.config_dir)
       .field("cache_dir", &self.cache_dir)
       .field("log_dir", &self.log_dir)
+      .field("runtime_dir", &self.runtime_dir)
+      .field("overrides", &self.overrides)
       .finish()
   }
 }
 
 impl FileHelper {
-  pub fn new() -> Self {
+  pub fn new(overrides: Overrides) -> Self {
     let config_dir = init_dir(dirs::config_dir().unwrap().join(APP_NAME));
     let cache_dir = init_dir(dirs::cache_dir().unwrap().join(APP_NAME));
     let log_dir = init_dir(dirs::data_dir().unwrap().join(APP_NAME));
     let systemd_dir = init_dir(dirs::config_dir().unwrap().join("systemd/user"));
+    let runtime_dir = dirs::runtime_dir().map(|dir| init_dir(dir.join(APP_NAME)));
 
     FileHelper {
       config_dir,
       cache_dir,
       log_dir,
       systemd_dir,
+      runtime_dir,
+      overrides,
+
+      // privates
+      cocoon: None,
+    }
+  }
+
+  /// a helper rooted somewhere other than the user's home, for tests
+  #[cfg(test)]
+  pub fn at(root: &std::path::Path) -> Self {
+    FileHelper {
+      config_dir: init_dir(root.join("config")),
+      cache_dir: init_dir(root.join("cache")),
+      log_dir: init_dir(root.join("log")),
+      systemd_dir: init_dir(root.join("systemd")),
+      runtime_dir: Some(init_dir(root.join("runtime"))),
+      overrides: Overrides::default(),
 
       // privates
       cocoon: None,
     }
   }
 
+  /// the config file in use, written out from the bundled one the first time. a `--config` file is
+  /// never created
   pub fn init_config(&self) -> PathBuf {
+    if let Some(path) = &self.overrides.config {
+      return path.clone();
+    }
+
     if !self.config_dir.join(CONFIG_FILE).exists() {
       let default = Resource::get(CONFIG_FILE).unwrap();
       fs::write(self.config_dir.join(CONFIG_FILE), default.data).unwrap();
@@ -98,40 +137,130 @@ impl FileHelper {
   }
 
   pub fn persist_clipboard(&self, clipboard: Vec<Item>) {
-    let encoded = bincode::serialize(&clipboard).unwrap();
+    let mut encoded = HISTORY_MAGIC.to_vec();
+    encoded.extend_from_slice(&HISTORY_VERSION.to_le_bytes());
+    encoded.extend(bincode::serialize(&clipboard).unwrap());
     let cocoon = self.cocoon.as_ref().unwrap();
 
-    let mut writer = self.get_clipboard_file();
+    // write then rename, so being stopped mid-write leaves the previous history in place
+    let path = self.clipboard_file();
+    let tmp = path.with_extension("bin.tmp");
+    let mut writer = fs::File::create(&tmp).unwrap();
     cocoon.dump(encoded, &mut writer).unwrap();
+    writer.sync_all().unwrap();
+    fs::rename(tmp, path).unwrap();
   }
 
   pub fn retrieve_clipboard(&self) -> Option<Vec<Item>> {
-    if !self.cache_dir.join("clipboard.bin").is_file() {
+    let path = self.clipboard_file();
+    if !path.is_file() {
       return None;
     }
 
-    let mut reader = self.get_clipboard_file();
+    let history = fs::read(&path)
+      .ok()
+      .and_then(|container| self.open_container(&container))
+      .and_then(|decrypted| decode_history(&decrypted));
+
+    if history.is_none() {
+      // move the unreadable file aside so the next save does not destroy it
+      let aside = path.with_extension("bin.corrupt");
+      error!(
+        "could not decrypt or decode clipboard history (wrong key?), moved it to {}",
+        aside.display()
+      );
+      let _ = fs::rename(&path, aside);
+    }
+
+    history
+  }
+
+  /// write an encrypted blob named after its content hash - existing blobs are left alone
+  pub fn persist_blob(&self, hash: &str, data: &[u8]) {
+    let path = self.blob_dir().join(hash);
+    if path.is_file() {
+      return;
+    }
+
     let cocoon = self.cocoon.as_ref().unwrap();
 
-    let decrypted = cocoon.parse(&mut reader).unwrap();
-    bincode::deserialize::<Vec<Item>>(&decrypted).ok()
+    // write then rename so a crash never leaves a half written blob behind
+    let tmp = path.with_extension("tmp");
+    let mut writer = fs::File::create(&tmp).unwrap();
+    cocoon.dump(data.to_vec(), &mut writer).unwrap();
+    writer.sync_all().unwrap();
+    fs::rename(tmp, path).unwrap();
+  }
+
+  pub fn retrieve_blob(&self, hash: &str) -> Option<Vec<u8>> {
+    let container = fs::read(self.blob_dir().join(hash)).ok()?;
+    self.open_container(&container)
+  }
+
+  /// delete every blob not in `referenced`, returning how many were removed
+  pub fn collect_blobs(&self, referenced: &HashSet<String>) -> usize {
+    let entries = match fs::read_dir(self.blob_dir()) {
+      Ok(entries) => entries,
+      Err(_) => return 0,
+    };
+
+    entries
+      .filter_map(Result::ok)
+      .filter(|entry| !referenced.contains(&*entry.file_name().to_string_lossy()))
+      .filter(|entry| fs::remove_file(entry.path()).is_ok())
+      .count()
   }
 
   pub fn init_cocoon(&mut self, config: &Config) {
     let seed = self.get_seed();
     let cocoon = generate_cocoon(seed, config.encryption.key.clone());
 
-    self.cocoon = Some(cocoon);
+    self.cocoon = Some(Arc::new(cocoon));
   }
 
-  fn get_clipboard_file(&self) -> fs::File {
-    let path = self.cache_dir.join("clipboard.bin");
-
-    if path.is_file() {
-      return fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+  /// decrypt a cocoon container, refusing ones whose header claims more data than the file holds -
+  /// cocoon trusts that length and would try to allocate it
+  fn open_container(&self, container: &[u8]) -> Option<Vec<u8>> {
+    // 12 byte nonce, 8 byte big endian length, 16 byte tag
+    let length = u64::from_be_bytes(container.get(12..20)?.try_into().ok()?);
+    if length.checked_add(36)? > container.len() as u64 {
+      return None;
     }
 
-    fs::File::create(path).unwrap()
+    let cocoon = self.cocoon.as_ref().unwrap();
+    cocoon.unwrap(container).ok()
+  }
+
+  fn clipboard_file(&self) -> PathBuf {
+    self.cache_dir.join("clipboard.bin")
+  }
+
+  fn blob_dir(&self) -> PathBuf {
+    init_dir(self.cache_dir.join("blobs"))
+  }
+
+  /// where png thumbnails are kept, named after the content hash of their image. there is nowhere
+  /// to keep them without a runtime directory
+  pub fn thumbnail_dir(&self) -> Option<PathBuf> {
+    Some(init_dir(self.runtime_dir.as_ref()?.join("thumbnails")))
+  }
+
+  /// remove thumbnails of images that are no longer in the history
+  pub fn collect_thumbnails(&self, referenced: &HashSet<String>) -> usize {
+    let entries = match self.runtime_dir.as_ref().map(|dir| fs::read_dir(dir.join("thumbnails"))) {
+      Some(Ok(entries)) => entries,
+      _ => return 0,
+    };
+
+    entries
+      .filter_map(Result::ok)
+      .filter(|entry| {
+        let name = entry.path();
+        let hash = name.file_stem().map(|stem| stem.to_string_lossy());
+        !hash.is_some_and(|hash| referenced.contains(&*hash))
+      })
+      .filter(|entry| fs::remove_file(entry.path()).is_ok())
+      .count()
   }
 
   fn get_seed_file(&self) -> fs::File {
@@ -163,6 +292,39 @@ impl FileHelper {
   }
 }
 
+/// starts every history file written since the format was versioned. older files start with the
+/// item count instead, which is never this large
+const HISTORY_MAGIC: &[u8; 8] = b"wayclip\0";
+
+/// layout of the history after the header. bump it whenever `Item` changes, and decode the previous
+/// version into the new one in `decode_history`
+const HISTORY_VERSION: u32 = 1;
+
+fn decode_history(decrypted: &[u8]) -> Option<Vec<Item>> {
+  let Some(versioned) = decrypted.strip_prefix(HISTORY_MAGIC) else {
+    return decode_unversioned(decrypted);
+  };
+  if versioned.len() < 4 {
+    return None;
+  }
+
+  let (version, items) = versioned.split_at(4);
+  match u32::from_le_bytes(version.try_into().unwrap()) {
+    1 => bincode::deserialize::<Vec<Item>>(items).ok(),
+    version => {
+      error!("the history was written by a newer wayclip (format version {})", version);
+      None
+    }
+  }
+}
+
+/// history files from before the header, all written by the first release
+fn decode_unversioned(decrypted: &[u8]) -> Option<Vec<Item>> {
+  bincode::deserialize::<Vec<LegacyItem>>(decrypted)
+    .ok()
+    .map(|legacy| legacy.into_iter().map(Item::from).collect())
+}
+
 fn init_dir(dir: PathBuf) -> PathBuf {
   if dir.is_dir() {
     return dir;
@@ -0,0 +1,255 @@
+use std::{collections::HashSet, fs};
+
+use proptest::{collection::vec, option, prelude::*};
+use serde::Serialize;
+
+use super::{decode_history, HISTORY_MAGIC, HISTORY_VERSION};
+use crate::clipboard::{content_hash, fixtures::helper, ImageItem, ImagePayload, Item, ItemData, TextItem};
+
+/// text that leans on the awkward cases: unicode, control characters and interior NULs
+fn text() -> impl Strategy<Value = String> {
+  prop_oneof![
+    any::<String>(),
+    "\\PC*",
+    (any::<String>(), any::<String>()).prop_map(|(a, b)| format!("{}\0{}", a, b)),
+  ]
+}
+
+fn mime() -> impl Strategy<Value = String> {
+  prop_oneof![
+    Just("text/plain".to_string()),
+    Just("text/plain;charset=utf-8".to_string()),
+    Just("image/png".to_string()),
+    any::<String>(),
+  ]
+}
+
+fn item() -> impl Strategy<Value = Item> {
+  let text_data = (text(), mime()).prop_map(|(text, mime)| ItemData::Text(TextItem { text, mime }));
+  let image_data = (vec(any::<u8>(), 0..512), mime(), any::<bool>()).prop_map(|(bytes, mime, blob)| {
+    let mut image = ImageItem::new(bytes, mime);
+    if blob {
+      image.payload = ImagePayload::Blob;
+    }
+
+    ItemData::Image(image)
+  });
+
+  let meta = (any::<bool>(), any::<u32>(), option::of(any::<u64>()), option::of(text()));
+
+  (text(), option::of(text()), prop_oneof![text_data, image_data], meta).prop_map(
+    |(id, seat, data, (pinned, uses, copied_at, rule))| Item {
+      pinned,
+      uses,
+      copied_at,
+      rule,
+      ..Item::new(id, seat, data)
+    },
+  )
+}
+
+#[test]
+fn persist_then_retrieve_round_trips() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+
+  proptest!(|(history in vec(item(), 0..32))| {
+    helper.persist_clipboard(history.clone());
+    prop_assert_eq!(helper.retrieve_clipboard(), Some(history));
+  });
+}
+
+#[test]
+fn blobs_round_trip_and_are_collected() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+
+  proptest!(|(blobs in vec(vec(any::<u8>(), 0..4096), 1..8), keep in any::<prop::sample::Index>())| {
+    let hashes = blobs.iter().map(|blob| content_hash(blob)).collect::<Vec<_>>();
+    for (hash, blob) in hashes.iter().zip(&blobs) {
+      helper.persist_blob(hash, blob);
+      prop_assert_eq!(helper.retrieve_blob(hash), Some(blob.clone()));
+    }
+
+    let kept = keep.get(&hashes).clone();
+    helper.collect_blobs(&HashSet::from([kept.clone()]));
+
+    for hash in &hashes {
+      prop_assert_eq!(helper.retrieve_blob(hash).is_some(), *hash == kept);
+    }
+  });
+}
+
+#[test]
+fn wrong_key_is_rejected_and_moved_aside() {
+  let dir = tempfile::tempdir().unwrap();
+  let history = vec![Item::new(
+    "1".to_string(),
+    None,
+    ItemData::Text(TextItem {
+      text: "secret".to_string(),
+      mime: "text/plain".to_string(),
+    }),
+  )];
+
+  helper(dir.path(), "right").persist_clipboard(history);
+
+  let wrong = helper(dir.path(), "wrong");
+  assert_eq!(wrong.retrieve_clipboard(), None);
+  assert!(dir.path().join("cache/clipboard.bin.corrupt").is_file());
+  assert!(!dir.path().join("cache/clipboard.bin").exists());
+}
+
+/// `Item` as the first release wrote it, before the format had a header
+#[derive(Serialize)]
+struct FirstItem {
+  id: String,
+  data: FirstItemData,
+}
+
+#[derive(Serialize)]
+enum FirstItemData {
+  Text(TextItem),
+  Image(FirstImageItem),
+}
+
+#[derive(Serialize)]
+struct FirstImageItem {
+  image: Vec<u8>,
+  mime: String,
+}
+
+#[test]
+fn first_release_history_still_loads() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+  let text = TextItem {
+    text: "first".to_string(),
+    mime: "text/plain".to_string(),
+  };
+  let old = vec![
+    FirstItem {
+      id: "1".to_string(),
+      data: FirstItemData::Text(text.clone()),
+    },
+    FirstItem {
+      id: "2".to_string(),
+      data: FirstItemData::Image(FirstImageItem {
+        image: vec![1, 2, 3],
+        mime: "image/png".to_string(),
+      }),
+    },
+  ];
+
+  // encrypted the way the first release did it, with the same cocoon
+  let mut file = fs::File::create(helper.clipboard_file()).unwrap();
+  let cocoon = helper.cocoon.as_ref().unwrap();
+  cocoon.dump(bincode::serialize(&old).unwrap(), &mut file).unwrap();
+  drop(file);
+
+  let history = helper.retrieve_clipboard().unwrap();
+
+  assert_eq!(history.len(), 2);
+  assert_eq!((history[0].id.as_str(), &history[0].data), ("1", &ItemData::Text(text)));
+  assert_eq!(
+    history[1].data,
+    ItemData::Image(ImageItem::new(vec![1, 2, 3], "image/png".to_string()))
+  );
+  assert!(history.iter().all(|item| item.seat.is_none() && item.copied_at.is_none() && !item.pinned));
+
+  // and is written back in the current format
+  helper.persist_clipboard(history.clone());
+  assert_eq!(helper.retrieve_clipboard(), Some(history));
+}
+
+#[test]
+fn history_is_read_by_its_format_version() {
+  let text = ItemData::Text(TextItem {
+    text: "versioned".to_string(),
+    mime: "text/plain".to_string(),
+  });
+  let items = vec![Item::new("1".to_string(), None, text)];
+  let versioned = |version: u32| {
+    let mut bytes = HISTORY_MAGIC.to_vec();
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.extend(bincode::serialize(&items).unwrap());
+    bytes
+  };
+
+  assert_eq!(decode_history(&versioned(HISTORY_VERSION)), Some(items.clone()));
+  assert_eq!(decode_history(&versioned(HISTORY_VERSION + 1)), None);
+  assert_eq!(decode_history(HISTORY_MAGIC), None);
+}
+
+// fuzz style properties: whatever is on disk, loading must never panic
+
+#[test]
+fn corrupted_history_never_panics() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+  let path = dir.path().join("cache/clipboard.bin");
+
+  proptest!(|(history in vec(item(), 1..8), flip in any::<prop::sample::Index>(), mask in 1..=255u8)| {
+    helper.persist_clipboard(history);
+
+    let mut bytes = fs::read(&path).unwrap();
+    let at = flip.index(bytes.len());
+    bytes[at] ^= mask;
+    fs::write(&path, bytes).unwrap();
+
+    prop_assert_eq!(helper.retrieve_clipboard(), None);
+  });
+}
+
+#[test]
+fn truncated_history_never_panics() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+  let path = dir.path().join("cache/clipboard.bin");
+
+  proptest!(|(history in vec(item(), 1..8), cut in any::<prop::sample::Index>())| {
+    helper.persist_clipboard(history);
+
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..cut.index(bytes.len())]).unwrap();
+
+    prop_assert_eq!(helper.retrieve_clipboard(), None);
+  });
+}
+
+#[test]
+fn arbitrary_files_never_panic() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+  let path = dir.path().join("cache/clipboard.bin");
+  fs::create_dir_all(dir.path().join("cache/blobs")).unwrap();
+
+  proptest!(|(bytes in vec(any::<u8>(), 0..1024))| {
+    fs::write(&path, &bytes).unwrap();
+    helper.retrieve_clipboard();
+
+    fs::write(dir.path().join("cache/blobs/fuzz"), &bytes).unwrap();
+    helper.retrieve_blob("fuzz");
+  });
+}
+
+#[test]
+fn huge_length_header_is_rejected() {
+  let dir = tempfile::tempdir().unwrap();
+  let helper = helper(dir.path(), "key");
+
+  for length in [u64::MAX, u64::MAX - 35, 1 << 40] {
+    let mut container = vec![0u8; 64];
+    container[12..20].copy_from_slice(&length.to_be_bytes());
+    fs::write(dir.path().join("cache/clipboard.bin"), &container).unwrap();
+
+    assert_eq!(helper.retrieve_clipboard(), None);
+  }
+}
+
+#[test]
+fn arbitrary_plaintext_never_panics() {
+  proptest!(|(bytes in vec(any::<u8>(), 0..1024))| {
+    decode_history(&bytes);
+  });
+}
@@ -1,12 +1,16 @@
-use self::{data::Config, file::FileHelper};
+use self::{data::Config, file::FileHelper, overrides::Overrides};
 
+pub mod check;
 pub mod cli;
 mod consts;
 pub mod data;
+pub mod edit;
 pub mod error;
 pub mod file;
 pub mod install;
+pub mod overrides;
 mod resources;
+pub mod watch;
 
 pub fn init(mut helper: FileHelper) -> (Config, FileHelper) {
   let config = Config::load(helper.clone());
@@ -15,6 +19,6 @@ pub fn init(mut helper: FileHelper) -> (Config, FileHelper) {
   (config, helper)
 }
 
-pub fn init_helper() -> FileHelper {
-  FileHelper::new()
+pub fn init_helper(overrides: Overrides) -> FileHelper {
+  FileHelper::new(overrides)
 }
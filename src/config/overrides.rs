@@ -0,0 +1,93 @@
+//! settings layered over the config file: `WAYCLIP_*` environment variables, then `--set` flags.
+//! `--config` swaps the file itself, so test instances and alternate profiles leave the user's
+//! config.toml alone.
+
+use std::path::PathBuf;
+
+use figment::{
+  providers::Env,
+  util::nest,
+  value::{Dict, Map, Value},
+  Error, Metadata, Profile, Provider,
+};
+
+/// prefix of the environment variables that override config keys
+pub const ENV_PREFIX: &str = "WAYCLIP_";
+
+/// what the command line changes about the config
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+  /// read this file instead of the one in the config directory
+  pub config: Option<PathBuf>,
+  /// `key=value` pairs from `--set`, later ones winning
+  pub set: Vec<(String, String)>,
+}
+
+impl Overrides {
+  /// the `WAYCLIP_*` environment variables, `WAYCLIP_GENERAL__MAX_HISTORY` setting `general.maxHistory`
+  pub fn env() -> Env {
+    Env::prefixed(ENV_PREFIX).lowercase(false).filter_map(|key| env_key(key.as_str()).map(Into::into))
+  }
+}
+
+impl Provider for Overrides {
+  fn metadata(&self) -> Metadata {
+    Metadata::named("`--set`")
+  }
+
+  fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+    let mut dict = Dict::new();
+    for (key, value) in &self.set {
+      // parsed like environment variables, so `100` is a number and `[1, 2]` an array
+      let value = nest(key, value.parse::<Value>().expect("infallible"));
+      if let Some(nested) = value.into_dict() {
+        merge(&mut dict, nested);
+      }
+    }
+
+    Ok(Profile::Default.collect(dict))
+  }
+}
+
+/// merge `other` into `dict`, recursing into tables so `a.b=1` and `a.c=2` both survive
+fn merge(dict: &mut Dict, other: Dict) {
+  for (key, value) in other {
+    match (dict.get_mut(&key), value) {
+      (Some(Value::Dict(_, existing)), Value::Dict(_, value)) => merge(existing, value),
+      (_, value) => {
+        dict.insert(key, value);
+      }
+    }
+  }
+}
+
+/// split a `--set` argument at its first `=`
+pub fn parse_setting(setting: &str) -> Result<(String, String), String> {
+  match setting.split_once('=') {
+    Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+    _ => Err(format!("expected `key=value`, e.g. `general.maxHistory=100`, got `{}`", setting)),
+  }
+}
+
+/// `GENERAL__MAX_HISTORY` to `general.maxHistory`: tables are split by `__`, words by `_`
+fn env_key(key: &str) -> Option<String> {
+  let parts = (key.split("__"))
+    .map(|part| {
+      let mut words = part.split('_').filter(|word| !word.is_empty()).map(str::to_lowercase);
+      let first = words.next()?;
+      Some(words.fold(first, |key, word| {
+        let mut chars = word.chars();
+        match chars.next() {
+          Some(c) => key + &c.to_uppercase().collect::<String>() + chars.as_str(),
+          None => key,
+        }
+      }))
+    })
+    .collect::<Option<Vec<_>>>()?;
+
+  // a single word like `WAYCLIP_LOG` is not a config key
+  (parts.len() > 1).then(|| parts.join("."))
+}
+
+#[cfg(test)]
+mod tests;
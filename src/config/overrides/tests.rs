@@ -0,0 +1,72 @@
+use std::fs;
+
+use super::{env_key, parse_setting, Overrides};
+use crate::config::data::Config;
+
+fn overrides(set: &[&str]) -> Overrides {
+  Overrides {
+    config: None,
+    set: set.iter().map(|setting| parse_setting(setting).unwrap()).collect(),
+  }
+}
+
+#[test]
+fn maps_environment_variables_to_keys() {
+  assert_eq!(env_key("GENERAL__MAX_HISTORY").as_deref(), Some("general.maxHistory"));
+  assert_eq!(env_key("BEMENU__GRAB_FOCUS").as_deref(), Some("bemenu.grabFocus"));
+  assert_eq!(env_key("PASTE__CHORD").as_deref(), Some("paste.chord"));
+  assert_eq!(env_key("LOG"), None);
+  assert_eq!(env_key("GENERAL__"), None);
+}
+
+#[test]
+fn settings_need_a_key_and_an_equals_sign() {
+  assert_eq!(
+    parse_setting("general.menu=fuzzel"),
+    Ok(("general.menu".to_string(), "fuzzel".to_string()))
+  );
+  assert_eq!(
+    parse_setting("paste.chord=ctrl+shift+v"),
+    Ok(("paste.chord".to_string(), "ctrl+shift+v".to_string()))
+  );
+  assert!(parse_setting("general.menu").is_err());
+  assert!(parse_setting("=fuzzel").is_err());
+}
+
+#[test]
+fn settings_win_over_the_file() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("config.toml");
+  fs::write(&path, "[general]\nmaxHistory = 10\nmenu = \"wofi\"\n").unwrap();
+
+  let config = Config::from_path(
+    &path,
+    &overrides(&["general.maxHistory=100", "bemenu.lines=3", "bemenu.grabFocus=false"]),
+  )
+  .unwrap();
+
+  assert_eq!(config.general.max_history, 100);
+  assert_eq!(config.general.menu, "wofi");
+  assert_eq!(config.bemenu.lines, 3);
+  assert!(!config.bemenu.grab_focus);
+}
+
+#[test]
+fn bad_settings_name_their_source() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("config.toml");
+  fs::write(&path, "").unwrap();
+
+  let invalid = Config::from_path(&path, &overrides(&["general.maxHistory=lots"])).unwrap_err();
+  assert_eq!(invalid.problems[0].key, "general.maxHistory");
+  assert!(invalid.problems[0].message.ends_with("(set by `--set`)"), "{}", invalid.problems[0].message);
+
+  let config = Config::from_path(&path, &overrides(&["general.maxHistroy=100", "bemenu.lines=3"])).unwrap();
+  let unknown = config.unknown().unwrap().problems;
+
+  assert_eq!(config.bemenu.lines, 3);
+  assert_eq!(unknown.len(), 1);
+  assert_eq!(unknown[0].key, "general.maxHistroy");
+  assert_eq!(unknown[0].line, None);
+  assert!(unknown[0].message.ends_with("(set by `--set`)"), "{}", unknown[0].message);
+}
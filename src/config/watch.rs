@@ -0,0 +1,109 @@
+//! reloads the config when its file changes. the directory is watched rather than the file, since
+//! most editors save by writing a new file and renaming it over the old one.
+
+use std::{
+  ffi::{CString, OsStr},
+  fs::{self, File},
+  io::Read,
+  os::{fd::FromRawFd, unix::ffi::OsStrExt},
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use tracing::{debug, warn};
+
+/// editors may write a file in several steps, so wait for them to settle before reading it
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// size of the fixed part of a `struct inotify_event`, the name follows it
+const EVENT_HEADER: usize = 16;
+
+/// call `on_change` on a new thread every time the contents of `path` change
+pub fn spawn(path: PathBuf, on_change: impl Fn() + Send + 'static) {
+  let Some(dir) = path.parent().map(Path::to_path_buf) else {
+    return;
+  };
+  let Some(name) = path.file_name().map(OsStr::to_os_string) else {
+    return;
+  };
+
+  let mut events = match watch(&dir) {
+    Ok(events) => events,
+    Err(e) => {
+      warn!("cannot watch {:?} for config changes: {}", dir, e);
+      return;
+    }
+  };
+
+  let mut last = fs::read(&path).ok();
+  std::thread::spawn(move || {
+    let mut buffer = [0u8; 4096];
+
+    loop {
+      let read = match events.read(&mut buffer) {
+        Ok(read) => read,
+        Err(e) => {
+          warn!("stopped watching {:?}: {}", path, e);
+          return;
+        }
+      };
+
+      if !names(&buffer[..read]).iter().any(|changed| changed == &name) {
+        continue;
+      }
+
+      std::thread::sleep(SETTLE);
+
+      // saving without changing anything, or several events for one save, is not a change
+      let contents = fs::read(&path).ok();
+      if contents.is_none() || contents == last {
+        continue;
+      }
+      last = contents;
+
+      debug!("{:?} changed", path);
+      on_change();
+    }
+  });
+}
+
+/// an inotify instance reporting files written or moved into `dir`
+fn watch(dir: &Path) -> std::io::Result<File> {
+  let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+  if fd < 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  let events = unsafe { File::from_raw_fd(fd) };
+
+  let dir = CString::new(dir.as_os_str().as_bytes())?;
+  if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) } < 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+
+  Ok(events)
+}
+
+/// the file names in a buffer of inotify events
+fn names(buffer: &[u8]) -> Vec<std::ffi::OsString> {
+  let mut names = vec![];
+  let mut offset = 0;
+
+  while offset + EVENT_HEADER <= buffer.len() {
+    let len = u32::from_ne_bytes(buffer[offset + 12..offset + 16].try_into().unwrap()) as usize;
+    let end = (offset + EVENT_HEADER + len).min(buffer.len());
+
+    // the name is padded with nuls
+    let name = &buffer[offset + EVENT_HEADER..end];
+    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+    if !name.is_empty() {
+      names.push(OsStr::from_bytes(name).to_os_string());
+    }
+
+    offset = end;
+  }
+
+  names
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,44 @@
+use std::{fs, sync::mpsc, time::Duration};
+
+use super::{names, spawn, EVENT_HEADER};
+
+fn event(name: &str, padded: usize) -> Vec<u8> {
+  let mut event = vec![0u8; EVENT_HEADER];
+  event[12..16].copy_from_slice(&(padded as u32).to_ne_bytes());
+  event.extend(name.as_bytes());
+  event.resize(EVENT_HEADER + padded, 0);
+  event
+}
+
+#[test]
+fn reads_padded_names() {
+  let mut buffer = event("config.toml", 16);
+  buffer.extend(event("", 0));
+  buffer.extend(event(".config.toml.swp", 32));
+
+  assert_eq!(names(&buffer), vec!["config.toml", ".config.toml.swp"]);
+}
+
+#[test]
+fn notices_writes_and_renames_but_not_rewrites() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("config.toml");
+  fs::write(&path, "a = 1").unwrap();
+
+  let (sender, changes) = mpsc::channel();
+  spawn(path.clone(), move || sender.send(()).unwrap());
+  let timeout = Duration::from_secs(5);
+
+  fs::write(&path, "a = 2").unwrap();
+  changes.recv_timeout(timeout).unwrap();
+
+  // how most editors save
+  let tmp = dir.path().join("config.toml.tmp");
+  fs::write(&tmp, "a = 3").unwrap();
+  fs::rename(&tmp, &path).unwrap();
+  changes.recv_timeout(timeout).unwrap();
+
+  fs::write(&path, "a = 3").unwrap();
+  fs::write(dir.path().join("other.toml"), "b = 1").unwrap();
+  assert!(changes.recv_timeout(Duration::from_millis(500)).is_err());
+}
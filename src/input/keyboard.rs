@@ -0,0 +1,403 @@
+//! typing text out through zwp_virtual_keyboard_v1, for windows that will not take a paste. every
+//! character gets a key of its own in a keymap made for the text, so anything unicode can be typed
+//! whatever layout the user has.
+
+use std::{
+  collections::HashMap,
+  ffi::CStr,
+  fmt,
+  fs::File,
+  io::{self, Write},
+  os::fd::{AsFd, FromRawFd},
+};
+
+use evdev::Key;
+use tracing::{debug, trace};
+use wayland_client::{
+  protocol::{
+    wl_keyboard::{KeyState, KeymapFormat},
+    wl_registry,
+    wl_seat::{self, WlSeat},
+  },
+  ConnectError, Connection, Dispatch, DispatchError, EventQueue, QueueHandle,
+};
+
+use super::Chord;
+use self::protocol::{
+  zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1, zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+
+#[allow(non_upper_case_globals, clippy::all)]
+mod protocol {
+  use wayland_client::{self, protocol::*};
+
+  pub mod __interfaces {
+    use wayland_client::{backend as wayland_backend, protocol::__interfaces::*};
+    wayland_scanner::generate_interfaces!("protocols/virtual-keyboard-unstable-v1.xml");
+  }
+  use self::__interfaces::*;
+
+  wayland_scanner::generate_client_code!("protocols/virtual-keyboard-unstable-v1.xml");
+}
+
+pub const KEYBOARD_MANAGER: &str = "zwp_virtual_keyboard_manager_v1";
+
+/// keycodes below this are reserved by xkb
+const FIRST_KEYCODE: u32 = 8;
+/// the highest keycode xkb keymaps reliably support, so at most this many different characters
+/// fit in one keymap
+const LAST_KEYCODE: u32 = 255;
+
+#[derive(Debug)]
+pub enum Error {
+  Connect(ConnectError),
+  Dispatch(DispatchError),
+  Unsupported,
+  NoSeat(Option<String>),
+  Keymap(io::Error),
+  UnknownKey(String),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Connect(e) => write!(f, "could not connect to the wayland compositor: {}", e),
+      Error::Dispatch(e) => write!(f, "lost connection to the wayland compositor: {}", e),
+      Error::Unsupported => write!(f, "the compositor does not support {}", KEYBOARD_MANAGER),
+      Error::NoSeat(Some(seat)) => write!(f, "there is no seat named {}", seat),
+      Error::NoSeat(None) => write!(f, "the compositor did not advertise a seat"),
+      Error::Keymap(e) => write!(f, "could not share a keymap with the compositor: {}", e),
+      Error::UnknownKey(chord) => write!(f, "{} has a key the virtual keyboard cannot press", chord),
+    }
+  }
+}
+
+impl From<ConnectError> for Error {
+  fn from(e: ConnectError) -> Self {
+    Error::Connect(e)
+  }
+}
+
+impl From<DispatchError> for Error {
+  fn from(e: DispatchError) -> Self {
+    Error::Dispatch(e)
+  }
+}
+
+#[derive(Default)]
+struct State {
+  seats: Vec<(WlSeat, Option<String>)>,
+  manager: Option<ZwpVirtualKeyboardManagerV1>,
+}
+
+/// a virtual keyboard on one seat, for as long as it takes to type something
+struct Session {
+  queue: EventQueue<State>,
+  state: State,
+  keyboard: ZwpVirtualKeyboardV1,
+  timer: std::time::Instant,
+}
+
+impl Session {
+  /// a keyboard on the seat named `seat`, or the first seat when there is no name
+  fn open(seat: Option<&str>) -> Result<Self, Error> {
+    let (mut queue, mut state) = connect()?;
+
+    let manager = state.manager.clone().ok_or(Error::Unsupported)?;
+    let chosen = match seat {
+      Some(seat) => state.seats.iter().find(|(_, name)| name.as_deref() == Some(seat)),
+      None => state.seats.first(),
+    };
+    let (wl_seat, _) = chosen.ok_or_else(|| Error::NoSeat(seat.map(str::to_string)))?;
+
+    let keyboard = manager.create_virtual_keyboard(wl_seat, &queue.handle(), ());
+    queue.roundtrip(&mut state)?;
+
+    Ok(Session {
+      queue,
+      state,
+      keyboard,
+      timer: std::time::Instant::now(),
+    })
+  }
+
+  /// the keymap has to be in place before any key of it is pressed
+  fn keymap(&mut self, keymap: &str) -> Result<(), Error> {
+    upload(&self.keyboard, keymap)?;
+    self.queue.roundtrip(&mut self.state)?;
+    Ok(())
+  }
+
+  fn key(&self, keycode: u32, state: KeyState) {
+    let time = self.timer.elapsed().as_millis() as u32;
+    self.keyboard.key(time, keycode - FIRST_KEYCODE, state.into());
+  }
+
+  fn close(mut self) -> Result<(), Error> {
+    self.keyboard.destroy();
+    self.queue.roundtrip(&mut self.state)?;
+    Ok(())
+  }
+}
+
+fn connect() -> Result<(EventQueue<State>, State), Error> {
+  let conn = Connection::connect_to_env()?;
+  let mut queue: EventQueue<State> = conn.new_event_queue();
+  let _registry = conn.display().get_registry(&queue.handle(), ());
+
+  // the second roundtrip gets the seat names
+  let mut state = State::default();
+  queue.roundtrip(&mut state)?;
+  queue.roundtrip(&mut state)?;
+
+  Ok((queue, state))
+}
+
+/// whether the compositor offers virtual keyboards
+pub fn available() -> bool {
+  match connect() {
+    Ok((_, state)) => state.manager.is_some(),
+    Err(e) => {
+      debug!("could not check for {}: {}", KEYBOARD_MANAGER, e);
+      false
+    }
+  }
+}
+
+/// type `text` on the seat named `seat`, or the first seat when there is no name
+pub fn type_out(text: &str, seat: Option<&str>) -> Result<(), Error> {
+  let mut session = Session::open(seat)?;
+
+  for chunk in chunks(text) {
+    let (keymap, keys) = keymap(&chunk);
+    session.keymap(&keymap)?;
+
+    for c in &chunk {
+      session.key(keys[c], KeyState::Pressed);
+      session.key(keys[c], KeyState::Released);
+    }
+    session.queue.roundtrip(&mut session.state)?;
+  }
+
+  debug!("typed out {} characters in {:?}", text.chars().count(), session.timer.elapsed());
+  session.close()
+}
+
+/// press `chord` on the seat named `seat`, for when there is no uinput device to do it
+pub fn press(chord: &Chord, seat: Option<&str>) -> Result<(), Error> {
+  let (keymap, keys, mask) = chord_keymap(chord).ok_or_else(|| Error::UnknownKey(format!("{:?}", chord)))?;
+
+  let mut session = Session::open(seat)?;
+  session.keymap(&keymap)?;
+
+  let modifiers = &keys[..keys.len() - 1];
+  for &keycode in modifiers {
+    session.key(keycode, KeyState::Pressed);
+  }
+  // not every compositor works the modifier state out from the keys alone
+  session.keyboard.modifiers(mask, 0, 0, 0);
+
+  let last = keys[keys.len() - 1];
+  session.key(last, KeyState::Pressed);
+  session.key(last, KeyState::Released);
+
+  for &keycode in modifiers.iter().rev() {
+    session.key(keycode, KeyState::Released);
+  }
+  session.keyboard.modifiers(0, 0, 0, 0);
+
+  session.close()
+}
+
+/// split the typable characters of `text` so that no piece has more different characters than one
+/// keymap can hold
+fn chunks(text: &str) -> Vec<Vec<char>> {
+  let limit = (LAST_KEYCODE - FIRST_KEYCODE) as usize;
+  let mut chunks = vec![];
+  let mut chunk = vec![];
+  let mut distinct = std::collections::HashSet::new();
+
+  for c in text.chars().filter(|&c| keysym(c).is_some()) {
+    if !distinct.contains(&c) && distinct.len() == limit {
+      chunks.push(std::mem::take(&mut chunk));
+      distinct.clear();
+    }
+
+    distinct.insert(c);
+    chunk.push(c);
+  }
+
+  if !chunk.is_empty() {
+    chunks.push(chunk);
+  }
+  chunks
+}
+
+/// the xkb name of the keysym that types `c`
+fn keysym(c: char) -> Option<String> {
+  match c {
+    '\n' => Some("Return".to_string()),
+    '\t' => Some("Tab".to_string()),
+    c if c.is_control() => None,
+    c => Some(format!("U{:04X}", c as u32)),
+  }
+}
+
+/// an xkb keymap with a key for every character in `chars`, and the keycode of each
+fn keymap(chars: &[char]) -> (String, HashMap<char, u32>) {
+  let mut keys = HashMap::new();
+  for &c in chars {
+    let next = FIRST_KEYCODE + 1 + keys.len() as u32;
+    keys.entry(c).or_insert(next);
+  }
+
+  let mut ordered = keys.iter().collect::<Vec<_>>();
+  ordered.sort_by_key(|(_, &code)| code);
+
+  let symbols = (ordered.iter())
+    .map(|(&c, &code)| (code, keysym(c).unwrap(), None))
+    .collect::<Vec<_>>();
+
+  (xkb_keymap(&symbols), keys)
+}
+
+/// a keymap for the keys of `chord`, their keycodes in order, and the modifier mask the chord holds
+fn chord_keymap(chord: &Chord) -> Option<(String, Vec<u32>, u32)> {
+  let mut symbols = vec![];
+  let mut mask = 0;
+
+  for (i, key) in chord.keys().enumerate() {
+    let code = FIRST_KEYCODE + 1 + i as u32;
+    let modifier = modifier(key);
+    if let Some((_, bit)) = modifier {
+      mask |= bit;
+    }
+
+    symbols.push((code, key_keysym(key)?, modifier.map(|(name, _)| name)));
+  }
+
+  let keys = symbols.iter().map(|(code, _, _)| *code).collect();
+  Some((xkb_keymap(&symbols), keys, mask))
+}
+
+/// the xkb modifier a key belongs to and its bit in the standard modifier mask
+fn modifier(key: Key) -> Option<(&'static str, u32)> {
+  match key {
+    Key::KEY_LEFTSHIFT => Some(("Shift", 1 << 0)),
+    Key::KEY_LEFTCTRL => Some(("Control", 1 << 2)),
+    Key::KEY_LEFTALT => Some(("Mod1", 1 << 3)),
+    Key::KEY_LEFTMETA => Some(("Mod4", 1 << 6)),
+    _ => None,
+  }
+}
+
+/// the xkb keysym name of an evdev key, for the keys chords are made of
+fn key_keysym(key: Key) -> Option<String> {
+  let name = match key {
+    Key::KEY_LEFTSHIFT => "Shift_L",
+    Key::KEY_LEFTCTRL => "Control_L",
+    Key::KEY_LEFTALT => "Alt_L",
+    Key::KEY_LEFTMETA => "Super_L",
+    Key::KEY_INSERT => "Insert",
+    Key::KEY_PASTE => "XF86Paste",
+    Key::KEY_ENTER => "Return",
+    Key::KEY_SPACE => "space",
+    Key::KEY_TAB => "Tab",
+    key => {
+      // letters, digits and function keys are named after themselves
+      let name = format!("{:?}", key);
+      let name = name.strip_prefix("KEY_")?;
+      return match name.len() {
+        1 => Some(name.to_lowercase()),
+        _ if name.starts_with('F') && name[1..].parse::<u8>().is_ok() => Some(name.to_string()),
+        _ => None,
+      };
+    }
+  };
+
+  Some(name.to_string())
+}
+
+/// a keymap binding each keycode to a keysym, and maybe to a modifier
+fn xkb_keymap(symbols: &[(u32, String, Option<&str>)]) -> String {
+  let keycodes = (symbols.iter())
+    .map(|(code, _, _)| format!("    <K{}> = {};\n", code, code))
+    .collect::<String>();
+  let keys = (symbols.iter())
+    .map(|(code, keysym, _)| format!("    key <K{}> {{ [ {} ] }};\n", code, keysym))
+    .collect::<String>();
+  let modifiers = (symbols.iter())
+    .filter_map(|(code, _, modifier)| Some(format!("    modifier_map {} {{ <K{}> }};\n", (*modifier)?, code)))
+    .collect::<String>();
+
+  format!(
+    "xkb_keymap {{\n  xkb_keycodes \"wayclip\" {{\n    minimum = {};\n    maximum = {};\n{}  }};\n  \
+     xkb_types \"wayclip\" {{ include \"complete\" }};\n  \
+     xkb_compatibility \"wayclip\" {{ include \"complete\" }};\n  \
+     xkb_symbols \"wayclip\" {{\n{}{}  }};\n}};\n",
+    FIRST_KEYCODE, LAST_KEYCODE, keycodes, keys, modifiers
+  )
+}
+
+/// hand `keymap` to the compositor through a memfd, null terminated as wl_keyboard keymaps are
+fn upload(keyboard: &ZwpVirtualKeyboardV1, keymap: &str) -> Result<(), Error> {
+  let name = CStr::from_bytes_with_nul(b"wayclip-keymap\0").unwrap();
+  let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+  if fd < 0 {
+    return Err(Error::Keymap(io::Error::last_os_error()));
+  }
+
+  let mut file = unsafe { File::from_raw_fd(fd) };
+  file.write_all(keymap.as_bytes()).map_err(Error::Keymap)?;
+  file.write_all(&[0]).map_err(Error::Keymap)?;
+
+  trace!("uploading a {} byte keymap", keymap.len() + 1);
+  keyboard.keymap(KeymapFormat::XkbV1.into(), file.as_fd(), keymap.len() as u32 + 1);
+
+  Ok(())
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+  fn event(
+    state: &mut Self,
+    registry: &wl_registry::WlRegistry,
+    event: wl_registry::Event,
+    _: &(),
+    _: &Connection,
+    qh: &QueueHandle<Self>,
+  ) {
+    if let wl_registry::Event::Global {
+      name,
+      interface,
+      version,
+    } = event
+    {
+      match &interface[..] {
+        "wl_seat" => {
+          let seat = registry.bind::<WlSeat, _, _>(name, version.min(2), qh, ());
+          state.seats.push((seat, None));
+        }
+        KEYBOARD_MANAGER => {
+          state.manager = Some(registry.bind::<ZwpVirtualKeyboardManagerV1, _, _>(name, 1, qh, ()));
+        }
+        _ => {}
+      }
+    }
+  }
+}
+
+impl Dispatch<WlSeat, ()> for State {
+  fn event(state: &mut Self, seat: &WlSeat, event: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    if let wl_seat::Event::Name { name } = event {
+      if let Some((_, seat_name)) = state.seats.iter_mut().find(|(known, _)| known == seat) {
+        *seat_name = Some(name);
+      }
+    }
+  }
+}
+
+wayland_client::delegate_noop!(State: ignore ZwpVirtualKeyboardManagerV1);
+wayland_client::delegate_noop!(State: ignore ZwpVirtualKeyboardV1);
+
+#[cfg(test)]
+mod tests;
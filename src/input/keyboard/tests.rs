@@ -0,0 +1,59 @@
+use super::{chord_keymap, chunks, keymap, FIRST_KEYCODE, LAST_KEYCODE};
+use crate::input::Chord;
+
+#[test]
+fn repeated_characters_share_a_key() {
+  let (map, keys) = keymap(&['a', 'b', 'a']);
+
+  assert_eq!(keys.len(), 2);
+  assert_eq!(keys[&'a'], FIRST_KEYCODE + 1);
+  assert_eq!(keys[&'b'], FIRST_KEYCODE + 2);
+  assert!(map.contains("key <K9> { [ U0061 ] };"));
+}
+
+#[test]
+fn unicode_and_whitespace_get_keysyms() {
+  let (map, _) = keymap(&['€', '\n', '\t']);
+
+  assert!(map.contains("[ U20AC ]"));
+  assert!(map.contains("[ Return ]"));
+  assert!(map.contains("[ Tab ]"));
+}
+
+#[test]
+fn control_characters_are_skipped() {
+  assert_eq!(chunks("a\u{7}b\r"), vec![vec!['a', 'b']]);
+}
+
+#[test]
+fn text_with_many_characters_is_split() {
+  let limit = (LAST_KEYCODE - FIRST_KEYCODE) as usize;
+  let text = (0..limit as u32 + 10).filter_map(|i| char::from_u32(0x4e00 + i)).collect::<String>();
+
+  let chunks = chunks(&text);
+
+  assert_eq!(chunks.len(), 2);
+  assert_eq!(chunks[0].len(), limit);
+  assert_eq!(chunks.concat().into_iter().collect::<String>(), text);
+  assert!(chunks.iter().all(|chunk| keymap(chunk).1.values().all(|&code| code <= LAST_KEYCODE)));
+}
+
+#[test]
+fn chords_hold_their_modifiers() {
+  let (map, keys, mask) = chord_keymap(&Chord::parse("ctrl+shift+v").unwrap()).unwrap();
+
+  assert_eq!(keys, vec![9, 10, 11]);
+  assert_eq!(mask, 0b101);
+  assert!(map.contains("key <K11> { [ v ] };"));
+  assert!(map.contains("modifier_map Control { <K9> };"));
+  assert!(map.contains("modifier_map Shift { <K10> };"));
+}
+
+#[test]
+fn chords_with_named_keys_can_be_pressed() {
+  for chord in ["shift+insert", "paste", "super+f5", "alt+1"] {
+    assert!(chord_keymap(&Chord::parse(chord).unwrap()).is_some(), "{}", chord);
+  }
+
+  assert!(chord_keymap(&Chord::parse("ctrl+kpplus").unwrap()).is_none());
+}
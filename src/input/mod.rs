@@ -1,45 +1,223 @@
+pub mod keyboard;
+
+use std::{
+  fmt, io,
+  str::FromStr,
+  sync::{Arc, RwLock},
+};
+
 use evdev::{
   uinput::{VirtualDevice, VirtualDeviceBuilder},
   AttributeSet, EventType, InputEvent, Key,
 };
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+/// what is pressed when a chord cannot be understood
+const FALLBACK: Key = Key::KEY_PASTE;
+
+/// keys held down while the last one of a chord is pressed, and released in reverse afterwards
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chord {
+  modifiers: Vec<Key>,
+  key: Key,
+}
+
+impl Chord {
+  /// parse chords like `ctrl+v`, `ctrl+shift+v` or `shift+insert`. modifiers are ctrl, shift, alt
+  /// and super, the key is a letter, a digit or the name of an evdev key without its `KEY_` prefix
+  pub fn parse(chord: &str) -> Option<Chord> {
+    let mut parts = chord.split('+').map(|part| part.trim().to_lowercase()).collect::<Vec<_>>();
+    let key = parts.pop().filter(|key| !key.is_empty())?;
+
+    let modifiers = (parts.iter())
+      .map(|modifier| match modifier.as_str() {
+        "ctrl" | "control" => Some(Key::KEY_LEFTCTRL),
+        "shift" => Some(Key::KEY_LEFTSHIFT),
+        "alt" => Some(Key::KEY_LEFTALT),
+        "super" | "logo" | "meta" => Some(Key::KEY_LEFTMETA),
+        _ => None,
+      })
+      .collect::<Option<Vec<_>>>()?;
+
+    let key = Key::from_str(&format!("KEY_{}", key.to_uppercase())).ok()?;
+
+    Some(Chord { modifiers, key })
+  }
+
+  pub fn keys(&self) -> impl DoubleEndedIterator<Item = Key> + '_ {
+    self.modifiers.iter().copied().chain([self.key])
+  }
+}
+
+impl Default for Chord {
+  fn default() -> Self {
+    Chord {
+      modifiers: vec![],
+      key: FALLBACK,
+    }
+  }
+}
+
+/// every key a chord may need, so the device never has to be rebuilt when the config changes
+fn supported_keys() -> AttributeSet<Key> {
+  let mut keys = AttributeSet::<Key>::new();
+  for code in Key::KEY_ESC.code()..=Key::KEY_COMPOSE.code() {
+    keys.insert(Key::new(code));
+  }
+  keys.insert(Key::KEY_PASTE);
+
+  keys
+}
+
+/// how selections get pasted, worked out at startup and again when nothing could press keys
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PasteMethod {
+  /// a uinput device presses the paste chord
+  Uinput,
+  /// a wayland virtual keyboard presses the paste chord
+  VirtualKeyboard,
+  /// nothing can press keys, selections are only copied
+  CopyOnly,
+}
+
+impl fmt::Display for PasteMethod {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PasteMethod::Uinput => write!(f, "uinput"),
+      PasteMethod::VirtualKeyboard => write!(f, "virtual keyboard ({})", keyboard::KEYBOARD_MANAGER),
+      PasteMethod::CopyOnly => write!(f, "copy only"),
+    }
+  }
+}
+
+/// the paste method in use, shared with the socket thread that reports it
+pub type WrappedPasteMethod = Arc<RwLock<PasteMethod>>;
+
+/// presses the paste chord with whatever this system allows
+pub struct Paster {
+  device: Option<UDevice>,
+  method: WrappedPasteMethod,
+}
+
+impl Paster {
+  /// prefer uinput, which works everywhere, then the compositor's virtual keyboards
+  pub fn new() -> Self {
+    let device = match UDevice::new() {
+      Ok(device) => Some(device),
+      Err(e) => {
+        warn!("could not create a uinput device ({}), trying the virtual keyboard protocol", e);
+        None
+      }
+    };
+
+    let method = match device {
+      Some(_) => PasteMethod::Uinput,
+      None => keyboard_method(),
+    };
+    if method == PasteMethod::CopyOnly {
+      warn!("nothing can press keys for now, selections will only be copied");
+    }
+
+    Paster {
+      device,
+      method: Arc::new(RwLock::new(method)),
+    }
+  }
+
+  pub fn method(&self) -> WrappedPasteMethod {
+    self.method.clone()
+  }
+
+  /// press `chord` to make the focused window on `seat` paste
+  pub fn paste(&mut self, chord: &Chord, seat: Option<&str>) {
+    if let Some(device) = &mut self.device {
+      return device.paste(chord);
+    }
+
+    // the compositor may not have been up yet when wayclip started
+    let mut method = self.method.write().unwrap();
+    if *method == PasteMethod::CopyOnly {
+      *method = keyboard_method();
+      if *method != PasteMethod::CopyOnly {
+        info!("pasting through {} from now on", method);
+      }
+    }
+
+    match *method {
+      PasteMethod::VirtualKeyboard => {
+        if let Err(e) = keyboard::press(chord, seat) {
+          warn!("could not paste: {}", e);
+        }
+      }
+      _ => debug!("not pasting, there is no way to press keys"),
+    }
+  }
+}
+
+/// the compositor's virtual keyboards if it has them, otherwise nothing
+fn keyboard_method() -> PasteMethod {
+  if keyboard::available() {
+    PasteMethod::VirtualKeyboard
+  } else {
+    PasteMethod::CopyOnly
+  }
+}
 
 pub struct UDevice {
   device: VirtualDevice,
+  supported: AttributeSet<Key>,
 }
 
 impl UDevice {
-  pub fn new() -> Self {
-    let mut keys = AttributeSet::<Key>::new();
-    keys.insert(Key::KEY_PASTE);
+  pub fn new() -> io::Result<Self> {
+    let supported = supported_keys();
 
-    let device = VirtualDeviceBuilder::new()
-      .unwrap()
+    let device = VirtualDeviceBuilder::new()?
       .name("wayclip")
-      .with_keys(&keys)
-      .unwrap()
-      .build()
-      .unwrap();
+      .with_keys(&supported)?
+      .build()?;
 
-    Self { device }
+    Ok(Self { device, supported })
   }
 
-  pub fn paste(&mut self) {
+  /// press `chord` to make the focused window paste
+  pub fn paste(&mut self, chord: &Chord) {
+    let chord = if chord.keys().all(|key| self.supported.contains(key)) {
+      chord.clone()
+    } else {
+      warn!("{:?} has keys the uinput device cannot press, using {:?} instead", chord, FALLBACK);
+      Chord::default()
+    };
+
     let type_ = EventType::KEY;
-    let code = Key::KEY_PASTE.code();
+    let down = chord.keys().map(|key| InputEvent::new(type_, key.code(), 1));
+    let up = chord.keys().rev().map(|key| InputEvent::new(type_, key.code(), 0));
+
+    for event in down.chain(up) {
+      if let Err(e) = self.device.emit(&[event]) {
+        warn!("could not press {:?}: {}", chord, e);
+        return;
+      }
+    }
+  }
+}
 
-    let down_event = InputEvent::new(type_, code, 1);
-    self.device.emit(&[down_event]).unwrap();
-    let up_event = InputEvent::new(type_, code, 0);
-    self.device.emit(&[up_event]).unwrap();
+/// put `data` on the clipboard of `seat`, or of every seat
+pub fn copy(data: Vec<u8>, mime: String, seat: Option<String>) {
+  let mut opts = wl_clipboard_rs::copy::Options::new();
+  if let Some(seat) = seat {
+    opts.seat(wl_clipboard_rs::copy::Seat::Specific(seat));
   }
 
-  pub fn copy(&self, text: String, mime: String) {
-    let opts = wl_clipboard_rs::copy::Options::new();
-    opts
-      .copy(
-        wl_clipboard_rs::copy::Source::Bytes(text.into_bytes().into()),
-        wl_clipboard_rs::copy::MimeType::Specific(mime),
-      )
-      .unwrap();
+  let copied = opts.copy(
+    wl_clipboard_rs::copy::Source::Bytes(data.into()),
+    wl_clipboard_rs::copy::MimeType::Specific(mime),
+  );
+  if let Err(e) = copied {
+    warn!("could not copy the selection: {}", e);
   }
 }
+
+#[cfg(test)]
+mod tests;
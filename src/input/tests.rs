@@ -0,0 +1,38 @@
+use evdev::Key;
+
+use super::{supported_keys, Chord};
+
+#[test]
+fn parses_modifiers_in_order() {
+  let chord = Chord::parse("ctrl+shift+v").unwrap();
+
+  assert_eq!(
+    chord.keys().collect::<Vec<_>>(),
+    vec![Key::KEY_LEFTCTRL, Key::KEY_LEFTSHIFT, Key::KEY_V]
+  );
+}
+
+#[test]
+fn parses_named_keys_case_insensitively() {
+  let chord = Chord::parse("Shift + Insert").unwrap();
+
+  assert_eq!(chord.keys().collect::<Vec<_>>(), vec![Key::KEY_LEFTSHIFT, Key::KEY_INSERT]);
+  assert_eq!(Chord::parse("paste"), Some(Chord::default()));
+}
+
+#[test]
+fn rejects_unknown_keys_and_modifiers() {
+  assert_eq!(Chord::parse("hyper+v"), None);
+  assert_eq!(Chord::parse("ctrl+nope"), None);
+  assert_eq!(Chord::parse("ctrl+"), None);
+  assert_eq!(Chord::parse(""), None);
+}
+
+#[test]
+fn common_chords_are_supported() {
+  let supported = supported_keys();
+
+  for chord in ["ctrl+v", "ctrl+shift+v", "shift+insert", "super+alt+1", "paste"] {
+    assert!(Chord::parse(chord).unwrap().keys().all(|key| supported.contains(key)), "{}", chord);
+  }
+}
@@ -1,22 +1,36 @@
 #![feature(if_let_guard)]
 
 use clap::Parser;
+use std::{sync::mpsc, time::Duration};
 
 mod clipboard;
 mod communication;
 mod config;
 mod input;
 mod menu;
+mod pick;
+mod search;
+mod shutdown;
 mod wayland;
 
-fn main() {
-  let helper = config::init_helper();
-  let _guard = init_logger(helper.log_dir.clone());
+/// how long stopping waits for the clipboard watcher before saving without it
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
 
+fn main() {
   let cli = config::cli::Cli::parse();
+  let helper = config::init_helper(cli.overrides());
+
+  // the picker owns the terminal, so it only logs to file
+  let console = !matches!(cli.command, Some(config::cli::Commands::Pick { .. }));
+  let _guard = init_logger(helper.log_dir.clone(), console);
 
   match &cli.command {
-    Some(config::cli::Commands::Toggle) => toggle(),
+    Some(config::cli::Commands::Toggle { seat, query, type_out }) => toggle(seat.clone(), query.clone(), *type_out),
+    Some(config::cli::Commands::Search { query, mode, limit }) => search(helper, query.clone(), *mode, *limit),
+    Some(config::cli::Commands::Pick { seat }) => pick(helper, seat.clone()),
+    Some(config::cli::Commands::Status) => status(),
+    Some(config::cli::Commands::Quit) => quit(),
+    Some(config::cli::Commands::Config { command }) => config_command(helper, command),
     Some(config::cli::Commands::Install) => config::install::install(helper),
     Some(config::cli::Commands::Dump) => dump(helper),
     Some(config::cli::Commands::Clear) => clear(helper),
@@ -25,40 +39,211 @@ fn main() {
 }
 
 fn run(helper: config::file::FileHelper) {
+  let shutdown = shutdown::Shutdown::default();
+  shutdown::on_signals(shutdown.clone());
+
   let (config, helper) = config::init(helper);
 
-  // bemenu -> wayland
-  let (tx, rx) = std::sync::mpsc::channel::<communication::MPSCMessage>();
+  // menus and the picker -> wayland
+  let (tx, rx) = mpsc::channel::<communication::MPSCMessage>();
 
-  let clipboard = clipboard::Clipboard::init(config, helper);
+  let path = config.path().to_path_buf();
+  let clipboard = clipboard::Clipboard::init(config, helper.clone());
   let t_clipboard = clipboard.clone();
 
+  let r_clipboard = clipboard.clone();
+  config::watch::spawn(path, move || match config::data::Config::try_load(helper.clone()) {
+    Ok(config) => {
+      config.warn_unknown();
+      clipboard::Clipboard::reload(&r_clipboard, config);
+      tracing::info!("reloaded the config");
+    }
+    Err(e) => tracing::error!("keeping the previous config, the new one is invalid:\n{}", e),
+  });
+
+  // capture keeps working without a way to press keys, selections are then only copied
+  let paster = input::Paster::new();
+  let paste_method = paster.method();
+
+  let s_clipboard = clipboard.clone();
+  let s_shutdown = shutdown.clone();
+  std::thread::spawn(move || {
+    communication::SocketHandler::server().listen(s_clipboard, tx, paste_method, s_shutdown);
+  });
+
+  let w_shutdown = shutdown.clone();
+  let watcher = std::thread::spawn(move || wayland::watch_clipboard(t_clipboard, rx, paster, w_shutdown));
+
+  // the watcher only returns once asked to stop, so ending any other way means it panicked. that
+  // stops wayclip with an error rather than keeping a daemon that records nothing
+  let (stopped, watcher_stopped) = mpsc::channel();
+  let m_shutdown = shutdown.clone();
   std::thread::spawn(move || {
-    communication::SocketHandler::server().listen(clipboard, tx);
+    let crashed = watcher.join().is_err() || !m_shutdown.requested();
+    let _ = stopped.send(crashed);
+    m_shutdown.request();
   });
 
-  wayland::watch_clipboard(t_clipboard, rx);
+  shutdown.wait();
+  communication::SocketHandler::stop();
+
+  // selections still being read land in the history before the last save
+  let crashed = watcher_stopped.recv_timeout(STOP_TIMEOUT).unwrap_or_else(|_| {
+    tracing::warn!("the clipboard watcher did not stop within {:?}", STOP_TIMEOUT);
+    false
+  });
+
+  let thumbnailer = clipboard.write().unwrap().close();
+  tracing::info!("saved the history, stopping");
+  if let Some(thumbnailer) = thumbnailer {
+    thumbnailer.stop();
+  }
+
+  if crashed {
+    tracing::error!("the clipboard watcher stopped unexpectedly");
+    std::process::exit(1);
+  }
 }
 
-fn toggle() {
-  communication::SocketHandler::client().toggle();
+fn toggle(seat: Option<String>, query: Option<String>, type_out: bool) {
+  communication::SocketHandler::client().toggle(seat, query, type_out);
+}
+
+fn search(helper: config::file::FileHelper, query: String, mode: Option<search::SearchMode>, limit: Option<usize>) {
+  use communication::{EntryContent, Request, Response};
+
+  let config = config::data::Config::load(helper);
+  let preview = menu::preview::Preview::new(&config.preview);
+
+  let entries = match communication::SocketHandler::client().request(Request::Search { query, mode }) {
+    Some(Response::Entries(entries)) => entries,
+    _ => std::process::exit(1),
+  };
+
+  for entry in entries.into_iter().take(limit.unwrap_or(usize::MAX)) {
+    let preview = match entry.content {
+      EntryContent::Text { preview: text, .. } => preview.text(&text),
+      EntryContent::Image { mime, size, dimensions, .. } => preview.image(&mime, dimensions, size, entry.copied_at),
+    };
+
+    println!("{}\t{}", entry.index, preview);
+  }
+}
+
+fn status() {
+  use communication::{Request, Response};
+
+  match communication::SocketHandler::client().request(Request::Status) {
+    Some(Response::Status(status)) => {
+      println!("menu: {}", status.menu);
+      println!("paste method: {}", status.paste_method);
+      println!("history items: {} ({} pinned)", status.items, status.pinned);
+    }
+    _ => std::process::exit(1),
+  }
+}
+
+fn quit() {
+  use communication::{Request, Response};
+
+  match communication::SocketHandler::client().request(Request::Quit) {
+    Some(Response::Done) => {}
+    _ => std::process::exit(1),
+  }
+}
+
+fn config_command(helper: config::file::FileHelper, command: &config::cli::ConfigCommands) {
+  use config::{cli::ConfigCommands, data::Config, edit};
+
+  let load = |helper| match Config::try_load(helper) {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  };
+
+  match command {
+    ConfigCommands::Check => {
+      let config = load(helper);
+      match config.unknown() {
+        // the daemon runs with the rest of the file, but a typo should still fail the check
+        Some(unknown) => {
+          eprintln!("{}", unknown);
+          std::process::exit(1);
+        }
+        None => println!("{}: ok", config.path().display()),
+      }
+    }
+    ConfigCommands::Path => println!("{}", helper.init_config().display()),
+    ConfigCommands::Get { key } => match edit::get(&load(helper), key) {
+      Ok(value) => println!("{}", value),
+      Err(e) => {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      }
+    },
+    ConfigCommands::Set { key, value } => {
+      if let Err(e) = edit::set(&helper.init_config(), key, value) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      }
+    }
+    ConfigCommands::Show { effective: false } => match std::fs::read_to_string(helper.init_config()) {
+      Ok(contents) => print!("{}", contents),
+      Err(e) => {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      }
+    },
+    ConfigCommands::Show { effective: true } => {
+      let mut table = None;
+      for setting in edit::effective(&load(helper)) {
+        if table.as_ref() != Some(&setting.table) {
+          if table.is_some() {
+            println!();
+          }
+          if !setting.table.is_empty() {
+            println!("[{}]", setting.table);
+          }
+          table = Some(setting.table.clone());
+        }
+
+        println!("{} = {} # {}", setting.name, setting.value, setting.source);
+      }
+    }
+  }
+}
+
+fn pick(helper: config::file::FileHelper, seat: Option<String>) {
+  let config = config::data::Config::load(helper);
+  pick::pick(seat, &config);
 }
 
 fn dump(helper: config::file::FileHelper) {
   let (config, helper) = config::init(helper);
 
-  let clipboard = clipboard::Clipboard::init(config, helper);
-  clipboard.read().unwrap().dump();
+  clipboard::Clipboard::open(config, helper).dump();
 }
 
 fn clear(helper: config::file::FileHelper) {
+  use communication::{Request, Response};
+
+  // the running daemon owns the history and its files, and would write its copy back
+  if let Some(mut client) = communication::SocketHandler::try_client() {
+    match client.request(Request::Clear) {
+      Some(Response::Done) => return,
+      _ => std::process::exit(1),
+    }
+  }
+
   let (config, helper) = config::init(helper);
 
   let clipboard = clipboard::Clipboard::init(config, helper);
   clipboard.write().unwrap().clear();
 }
 
-fn init_logger(log_dir: std::path::PathBuf) -> tracing_appender::non_blocking::WorkerGuard {
+fn init_logger(log_dir: std::path::PathBuf, console: bool) -> tracing_appender::non_blocking::WorkerGuard {
   use tracing::metadata::LevelFilter;
   use tracing_subscriber::{
     filter::Directive, fmt, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
@@ -98,7 +283,7 @@ fn init_logger(log_dir: std::path::PathBuf) -> tracing_appender::non_blocking::W
     .parse_lossy(filter_directives);
 
   tracing_subscriber::registry()
-    .with(fmt::layer())
+    .with(console.then(fmt::layer))
     .with(fmt::layer().with_writer(non_blocking).with_filter(filter))
     .init();
 
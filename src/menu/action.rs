@@ -0,0 +1,112 @@
+//! what can be done with a history item once it is chosen. plain selection pastes it, other
+//! actions are bound to a backend's custom keys through `actions.custom`.
+
+use std::{fs, io::Write, os::unix::fs::OpenOptionsExt, process::Command};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+  clipboard::{self, ItemData},
+  config::data::Actions,
+};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Action {
+  /// copy the item and paste it into the focused window
+  #[default]
+  Paste,
+  /// copy the item without pasting it
+  Copy,
+  /// remove the item from the history
+  Delete,
+  /// pin the item, or unpin it if it already is
+  Pin,
+  /// open the item in an editor and keep what comes back
+  Edit,
+  /// type the item out key by key instead of pasting it
+  TypeOut,
+}
+
+impl Action {
+  /// the action bound to custom key `key` (counting from 1), if any
+  pub fn custom(config: &Actions, key: usize) -> Option<Action> {
+    config.custom.get(key.checked_sub(1)?).copied()
+  }
+}
+
+/// open the text item at menu index `idx` in the configured editor and store the result as the
+/// newest item. returns the key of the edited item, or nothing if nothing changed
+pub fn edit(clipboard: &clipboard::WrappedClipboard, idx: usize) -> Option<String> {
+  let (id, original, path, editor) = {
+    let borrow = clipboard.read().unwrap();
+    let item = borrow.item_at(idx)?;
+
+    let ItemData::Text(text) = &item.data else {
+      warn!("only text items can be edited");
+      return None;
+    };
+
+    let name = format!("edit-{}-{}.txt", std::process::id(), idx);
+    let Some(path) = borrow.scratch_path(&name) else {
+      warn!("editing needs $XDG_RUNTIME_DIR, anywhere else the text would outlive the session unencrypted");
+      return None;
+    };
+
+    (item.id.clone(), text.text.clone(), path, borrow.get_config().actions.editor)
+  };
+
+  // the runtime directory is private to the user, the file is too since it holds clipboard data
+  let written = fs::OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .mode(0o600)
+    .open(&path)
+    .and_then(|mut file| file.write_all(original.as_bytes()));
+  if let Err(e) = written {
+    warn!("could not write {:?} for editing: {}", path, e);
+    return None;
+  }
+
+  let command = editor.replace("{file}", &format!("'{}'", path.display().to_string().replace('\'', r"'\''")));
+  debug!("editing index {} with {:?}", idx, command);
+  let status = Command::new("sh").arg("-c").arg(&command).status();
+
+  let edited = fs::read_to_string(&path);
+  let _ = fs::remove_file(&path);
+
+  match status {
+    Ok(status) if status.success() => {}
+    Ok(status) => {
+      warn!("editor exited with {}, keeping the original", status);
+      return None;
+    }
+    Err(e) => {
+      warn!("failed to start editor: {}", e);
+      return None;
+    }
+  }
+
+  let edited = match edited {
+    Ok(edited) => edited,
+    Err(e) => {
+      warn!("could not read back {:?}: {}", path, e);
+      return None;
+    }
+  };
+
+  // editors like to add a final newline the original did not have
+  let edited = if original.ends_with('\n') {
+    edited
+  } else {
+    edited.strip_suffix('\n').unwrap_or(&edited).to_string()
+  };
+
+  if edited == original || edited.is_empty() {
+    return None;
+  }
+
+  clipboard.write().unwrap().replace_text(&id, &original, edited)
+}
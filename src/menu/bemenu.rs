@@ -6,9 +6,9 @@
 use crate::{clipboard, config::error::Error};
 use std::ffi::CString;
 use std::mem;
-use tracing::trace;
+use tracing::{debug, trace};
 
-use super::Menu;
+use super::{preview::Preview, Action, Chosen, Menu};
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
@@ -26,11 +26,11 @@ impl Menu for BeMenu {
     Ok(Box::new(Self { clipboard }))
   }
 
-  fn show(&self) -> Result<Option<(String, usize)>, Error> {
+  fn show(&self, query: &str) -> Result<Option<(Action, Chosen)>, Error> {
     trace!("Starting BeMenu show");
     let menu = unsafe { bm_menu_new(std::ptr::null()) };
     self.handle_config(menu);
-    self.add_items(menu);
+    let items = self.add_items(menu, query);
 
     unsafe {
       let mut unicode = 0u32;
@@ -56,10 +56,23 @@ impl Menu for BeMenu {
       }
       trace!("Exited BeMenu event loop");
 
-      if status == bm_run_result_BM_RUN_RESULT_SELECTED {
+      let action = if status == bm_run_result_BM_RUN_RESULT_SELECTED {
+        Some(Action::Paste)
+      } else if (bm_run_result_BM_RUN_RESULT_CUSTOM_1..=bm_run_result_BM_RUN_RESULT_CUSTOM_10).contains(&status) {
+        // alt+1 through alt+0
+        let key = (status - bm_run_result_BM_RUN_RESULT_CUSTOM_1) as usize + 1;
+        let action = Action::custom(&self.clipboard.read().unwrap().get_config().actions, key);
+        if action.is_none() {
+          debug!("no action bound to custom key {}", key);
+        }
+        action
+      } else {
+        None
+      };
+
+      if let Some(action) = action {
         let selected = *bm_menu_get_selected_items(menu, std::ptr::null_mut());
         let selected_idx = bm_item_get_userdata(selected) as *mut usize;
-        let text = std::ffi::CStr::from_ptr(bm_item_get_text(selected)).to_str().unwrap();
         let index = if selected_idx.is_null() {
           0usize
         } else {
@@ -68,10 +81,8 @@ impl Menu for BeMenu {
           libc::free(selected_idx.cast());
           value
         };
-        let owned = (text.to_string(), index);
-
         bm_menu_free(menu);
-        return Ok(Some(owned));
+        return Ok(items.into_iter().find(|item| item.index == index).map(|item| (action, item)));
       } else if status == bm_run_result_BM_RUN_RESULT_CANCEL {
         bm_menu_free(menu);
         return Ok(None);
@@ -101,19 +112,36 @@ impl BeMenu {
     }
   }
 
-  fn add_items(&self, menu: *mut bm_menu) {
+  /// fill the menu, returning the items as they are offered
+  fn add_items(&self, menu: *mut bm_menu, query: &str) -> Vec<Chosen> {
     self.remove_items(menu);
 
     trace!("adding items to menu");
-    for (idx, item) in (self.clipboard.read().unwrap().hist).iter().rev().enumerate() {
-      // trace!("Processing item at index {}", idx);
-      match &item.data {
-        clipboard::ItemData::Text(data) => unsafe {
-          self.add_text_item(menu, data, idx);
-        },
-        clipboard::ItemData::Image(_) => {}
-      }
+    let borrow = self.clipboard.read().unwrap();
+    let config = borrow.get_config();
+    let preview = Preview::new(&config.preview);
+    let mut items = vec![];
+
+    for idx in borrow.search(query, config.search.mode) {
+      let Some(item) = borrow.item_at(idx) else {
+        continue;
+      };
+
+      let label = match &item.data {
+        clipboard::ItemData::Text(data) => preview.text(&data.text),
+        clipboard::ItemData::Image(image) => {
+          preview.image(&image.mime, borrow.image_dimensions(image), image.size, item.copied_at)
+        }
+      };
+
+      unsafe { self.add_item(menu, &label, idx) };
+      items.push(Chosen {
+        index: idx,
+        key: item.key(),
+      });
     }
+
+    items
   }
 
   fn remove_items(&self, menu: *mut bm_menu) {
@@ -122,8 +150,8 @@ impl BeMenu {
     unsafe { bm_menu_free_items(menu) }
   }
 
-  unsafe fn add_text_item(&self, menu: *mut bm_menu, text_item: &clipboard::TextItem, idx: usize) {
-    let c_string = match CString::new(text_item.text.clone()) {
+  unsafe fn add_item(&self, menu: *mut bm_menu, text: &str, idx: usize) {
+    let c_string = match CString::new(text) {
       Ok(value) => value,
       Err(_) => return,
     };
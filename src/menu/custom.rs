@@ -0,0 +1,132 @@
+//! a menu backed by any command the user configures. entries go in as `<index>\t<preview>` lines
+//! (or one json object per line) and the command answers with the index it picked, so what gets
+//! pasted always comes from the history rather than from the preview text. an action other than
+//! paste is asked for with the same exit codes rofi uses for custom keys, or an `action` field in
+//! the json format.
+
+use std::{path::Path, process::Command};
+
+use serde::{Deserialize, Serialize};
+use tracing::{trace, warn};
+
+use super::{
+  dmenu::{entries, parse_index, run, Entry},
+  Action, Chosen, Menu,
+};
+use crate::{
+  clipboard,
+  config::{
+    data::{CustomMenuConfig, CustomMenuFormat},
+    error::Error,
+  },
+};
+
+pub struct Custom {
+  clipboard: clipboard::WrappedClipboard,
+}
+
+/// one line of input in the json format
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+  index: usize,
+  preview: &'a str,
+  /// png thumbnail, for images
+  #[serde(skip_serializing_if = "Option::is_none")]
+  icon: Option<&'a Path>,
+}
+
+/// what a command may answer with in the json format, besides a bare index or a tsv line
+#[derive(Deserialize)]
+struct JsonChoice {
+  index: usize,
+  #[serde(default)]
+  action: Option<Action>,
+}
+
+impl Menu for Custom {
+  fn new(clipboard: clipboard::WrappedClipboard) -> Result<Box<Self>, Error> {
+    trace!("Initializing custom menu");
+    if clipboard.read().unwrap().get_config().custom.command.trim().is_empty() {
+      return Err(Error::Launcher("the custom menu needs a command".into()));
+    }
+
+    Ok(Box::new(Self { clipboard }))
+  }
+
+  fn show(&self, query: &str) -> Result<Option<(Action, Chosen)>, Error> {
+    let config = self.clipboard.read().unwrap().get_config().custom;
+    let entries = entries(&self.clipboard, query);
+
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(expand(&config));
+
+    let output = match run(command, &config.command, input(&entries, &config.format))? {
+      Some(output) => output,
+      None => return Ok(None),
+    };
+
+    let (index, action) = match parse_choice(&output.line, &config.format) {
+      Some(choice) => choice,
+      None => {
+        warn!("custom menu returned something that is not an index: {:?}", output.line);
+        return Ok(None);
+      }
+    };
+
+    let Some(entry) = entries.into_iter().find(|entry| entry.index == index) else {
+      warn!("custom menu picked index {} which is not in the history", index);
+      return Ok(None);
+    };
+
+    let action = match action {
+      Some(action) => action,
+      None => match output.action(&self.clipboard.read().unwrap().get_config()) {
+        Some(action) => action,
+        None => return Ok(None),
+      },
+    };
+
+    Ok(Some((action, entry.chosen())))
+  }
+}
+
+/// fill in `{prompt}` and `{lines}` in the command template
+fn expand(config: &CustomMenuConfig) -> String {
+  config
+    .command
+    .replace("{prompt}", &quote(&config.prompt))
+    .replace("{lines}", &config.lines.to_string())
+}
+
+/// single quote a value for sh
+fn quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn input(entries: &[Entry], format: &CustomMenuFormat) -> String {
+  let lines = entries.iter().map(|entry| match format {
+    CustomMenuFormat::Tsv => format!("{}\t{}", entry.index, entry.line),
+    CustomMenuFormat::Json => serde_json::to_string(&JsonEntry {
+      index: entry.index,
+      preview: &entry.line,
+      icon: entry.icon.as_deref(),
+    })
+    .unwrap(),
+  });
+
+  lines.collect::<Vec<_>>().join("\n")
+}
+
+/// accept the whole line echoed back, just the index, or a json object with an index and maybe an
+/// action
+fn parse_choice(selected: &str, format: &CustomMenuFormat) -> Option<(usize, Option<Action>)> {
+  let selected = selected.trim();
+
+  if let CustomMenuFormat::Json = format {
+    if let Ok(choice) = serde_json::from_str::<JsonChoice>(selected) {
+      return Some((choice.index, choice.action));
+    }
+  }
+
+  Some((parse_index(selected)?, None))
+}
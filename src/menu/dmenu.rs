@@ -0,0 +1,347 @@
+//! menus backed by external launchers that speak the dmenu protocol: one entry per line on stdin,
+//! the chosen line on stdout. previews of different items can be the same, so the chosen item is
+//! found by its position, or by the index each line starts with for launchers that only print lines.
+
+use std::{
+  env,
+  io::Write,
+  marker::PhantomData,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+};
+
+use tracing::{debug, trace, warn};
+
+use super::{preview::Preview, Action, Chosen, Menu};
+use crate::{
+  clipboard,
+  config::{
+    data::{Config, LauncherConfig},
+    error::Error,
+  },
+};
+
+/// how to drive a particular launcher in dmenu mode
+pub trait Launcher: Send {
+  /// name of the launcher, also the binary run when no command is configured
+  const NAME: &'static str;
+  /// whether the launcher understands rofi's `<label>\0icon\x1f<path>` lines
+  const ICONS: bool = false;
+  /// whether `args` make the launcher print the position of the chosen line instead of the line
+  const PRINTS_POSITION: bool = false;
+
+  fn config(config: &Config) -> LauncherConfig;
+
+  /// flags that put the launcher in dmenu mode with the configured prompt and height
+  fn args(config: &LauncherConfig) -> Vec<String>;
+}
+
+pub struct Fuzzel;
+pub struct Wofi;
+pub struct Rofi;
+pub struct Tofi;
+pub struct Dmenu;
+
+impl Launcher for Fuzzel {
+  const NAME: &'static str = "fuzzel";
+  const ICONS: bool = true;
+  const PRINTS_POSITION: bool = true;
+
+  fn config(config: &Config) -> LauncherConfig {
+    config.fuzzel.clone()
+  }
+
+  fn args(config: &LauncherConfig) -> Vec<String> {
+    let mut args = vec!["--dmenu".to_string(), "--index".to_string()];
+    push_flag(&mut args, "--prompt", &config.prompt);
+    push_lines(&mut args, "--lines", config.lines);
+    args
+  }
+}
+
+impl Launcher for Wofi {
+  const NAME: &'static str = "wofi";
+
+  fn config(config: &Config) -> LauncherConfig {
+    config.wofi.clone()
+  }
+
+  fn args(config: &LauncherConfig) -> Vec<String> {
+    let mut args = vec!["--dmenu".to_string(), "--insensitive".to_string()];
+    push_flag(&mut args, "--prompt", &config.prompt);
+    push_lines(&mut args, "--lines", config.lines);
+    args
+  }
+}
+
+impl Launcher for Rofi {
+  const NAME: &'static str = "rofi";
+  const ICONS: bool = true;
+  const PRINTS_POSITION: bool = true;
+
+  fn config(config: &Config) -> LauncherConfig {
+    config.rofi.clone()
+  }
+
+  fn args(config: &LauncherConfig) -> Vec<String> {
+    let mut args = vec![
+      "-dmenu".to_string(),
+      "-i".to_string(),
+      "-show-icons".to_string(),
+      "-format".to_string(),
+      "i".to_string(),
+    ];
+    push_flag(&mut args, "-p", &config.prompt);
+    push_lines(&mut args, "-l", config.lines);
+    args
+  }
+}
+
+impl Launcher for Tofi {
+  const NAME: &'static str = "tofi";
+
+  fn config(config: &Config) -> LauncherConfig {
+    config.tofi.clone()
+  }
+
+  // tofi is always in dmenu mode when run under its own name
+  fn args(config: &LauncherConfig) -> Vec<String> {
+    let mut args = vec![];
+    push_flag(&mut args, "--prompt-text", &config.prompt);
+    push_lines(&mut args, "--num-results", config.lines);
+    args
+  }
+}
+
+impl Launcher for Dmenu {
+  const NAME: &'static str = "dmenu";
+
+  fn config(config: &Config) -> LauncherConfig {
+    config.dmenu.clone()
+  }
+
+  fn args(config: &LauncherConfig) -> Vec<String> {
+    let mut args = vec!["-i".to_string()];
+    push_flag(&mut args, "-p", &config.prompt);
+    push_lines(&mut args, "-l", config.lines);
+    args
+  }
+}
+
+fn push_flag(args: &mut Vec<String>, flag: &str, value: &str) {
+  if !value.is_empty() {
+    args.push(flag.to_string());
+    args.push(value.to_string());
+  }
+}
+
+fn push_lines(args: &mut Vec<String>, flag: &str, lines: u32) {
+  if lines > 0 {
+    args.push(flag.to_string());
+    args.push(lines.to_string());
+  }
+}
+
+pub struct DMenu<L: Launcher> {
+  clipboard: clipboard::WrappedClipboard,
+  launcher: PhantomData<L>,
+}
+
+/// a history entry as shown in the launcher
+pub(super) struct Entry {
+  pub index: usize,
+  pub key: String,
+  pub line: String,
+  /// thumbnail for image entries
+  pub icon: Option<PathBuf>,
+}
+
+impl<L: Launcher> Menu for DMenu<L> {
+  fn new(clipboard: clipboard::WrappedClipboard) -> Result<Box<Self>, Error> {
+    trace!("Initializing {}", L::NAME);
+    let config = L::config(&clipboard.read().unwrap().get_config());
+    let program = config.command.unwrap_or_else(|| L::NAME.to_string());
+
+    if !on_path(&program) {
+      return Err(Error::Launcher(format!("could not find {} ({})", L::NAME, program)));
+    }
+
+    Ok(Box::new(Self {
+      clipboard,
+      launcher: PhantomData,
+    }))
+  }
+
+  fn show(&self, query: &str) -> Result<Option<(Action, Chosen)>, Error> {
+    let config = L::config(&self.clipboard.read().unwrap().get_config());
+    let entries = entries(&self.clipboard, query);
+
+    let program = config.command.clone().unwrap_or_else(|| L::NAME.to_string());
+    let mut command = Command::new(&program);
+    command.args(L::args(&config)).args(&config.args);
+
+    let output = match run(command, &program, input::<L>(&entries))? {
+      Some(output) => output,
+      None => return Ok(None),
+    };
+    let Some(action) = output.action(&self.clipboard.read().unwrap().get_config()) else {
+      return Ok(None);
+    };
+
+    match chosen::<L>(entries, &output.line) {
+      Some(entry) => Ok(Some((action, entry.chosen()))),
+      None => {
+        warn!("{} returned something that is not in the history: {:?}", program, output.line);
+        Ok(None)
+      }
+    }
+  }
+}
+
+/// one line per entry, starting with its menu index unless the launcher prints positions
+fn input<L: Launcher>(entries: &[Entry]) -> String {
+  (entries.iter())
+    .map(|entry| {
+      let line = if L::PRINTS_POSITION {
+        entry.line.clone()
+      } else {
+        format!("{}\t{}", entry.index, entry.line)
+      };
+
+      match &entry.icon {
+        Some(icon) if L::ICONS => format!("{}\0icon\x1f{}", line, icon.display()),
+        _ => line,
+      }
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// the entry a launcher's answer points at
+fn chosen<L: Launcher>(entries: Vec<Entry>, output: &str) -> Option<Entry> {
+  if L::PRINTS_POSITION {
+    entries.into_iter().nth(output.trim().parse().ok()?)
+  } else {
+    let index = parse_index(output)?;
+    entries.into_iter().find(|entry| entry.index == index)
+  }
+}
+
+/// the menu index a `<index>\t<preview>` line starts with
+pub(super) fn parse_index(line: &str) -> Option<usize> {
+  line.split('\t').next()?.trim().parse().ok()
+}
+
+impl Entry {
+  pub fn chosen(self) -> Chosen {
+    Chosen {
+      index: self.index,
+      key: self.key,
+    }
+  }
+}
+
+/// what a launcher answered with
+pub(super) struct Output {
+  pub line: String,
+  /// the custom key the entry was chosen with (counting from 1), if not plain enter
+  pub custom: Option<usize>,
+}
+
+impl Output {
+  /// what the user asked for: paste on enter, otherwise whatever the custom key is bound to
+  pub fn action(&self, config: &Config) -> Option<Action> {
+    match self.custom {
+      None => Some(Action::Paste),
+      Some(key) => {
+        let action = Action::custom(&config.actions, key);
+        if action.is_none() {
+          debug!("no action bound to custom key {}", key);
+        }
+        action
+      }
+    }
+  }
+}
+
+/// exit codes rofi and fuzzel use for their custom keys, 10 being custom key 1
+const CUSTOM_EXIT_CODES: std::ops::RangeInclusive<i32> = 10..=28;
+
+/// run a launcher with `input` on stdin, returning the line it printed or `None` if it was dismissed
+pub(super) fn run(mut command: Command, program: &str, input: String) -> Result<Option<Output>, Error> {
+  let mut child = command
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .map_err(|e| Error::Launcher(format!("failed to start {}: {}", program, e)))?;
+
+  // write from another thread so a launcher that answers before reading everything cannot block us
+  let mut stdin = child.stdin.take().unwrap();
+  let writer = std::thread::spawn(move || {
+    let _ = stdin.write_all(input.as_bytes());
+  });
+
+  let output = child
+    .wait_with_output()
+    .map_err(|e| Error::Launcher(format!("{} did not exit cleanly: {}", program, e)))?;
+  let _ = writer.join();
+
+  // dmenu-style launchers exit non-zero when dismissed, and some with a code of their own for
+  // custom keys
+  let custom = match output.status.code() {
+    Some(0) => None,
+    Some(code) if CUSTOM_EXIT_CODES.contains(&code) => Some((code - CUSTOM_EXIT_CODES.start() + 1) as usize),
+    _ => {
+      debug!("{} exited with {}", program, output.status);
+      return Ok(None);
+    }
+  };
+
+  let selected = String::from_utf8_lossy(&output.stdout);
+  Ok(Some(Output {
+    line: selected.trim_end_matches('\n').to_string(),
+    custom,
+  }))
+}
+
+/// the items matching `query`, best first
+pub(super) fn entries(clipboard: &clipboard::WrappedClipboard, query: &str) -> Vec<Entry> {
+  let borrow = clipboard.read().unwrap();
+  let config = borrow.get_config();
+  let preview = Preview::new(&config.preview);
+
+  (borrow.search(query, config.search.mode))
+    .into_iter()
+    .filter_map(|index| {
+      let item = borrow.item_at(index)?;
+      let (line, icon) = match &item.data {
+        clipboard::ItemData::Text(data) => (preview.text(&data.text), None),
+        clipboard::ItemData::Image(image) => (
+          preview.image(&image.mime, borrow.image_dimensions(image), image.size, item.copied_at),
+          borrow.thumbnail(image),
+        ),
+      };
+
+      Some(Entry {
+        index,
+        key: item.key(),
+        line,
+        icon,
+      })
+    })
+    .filter(|entry| !entry.line.is_empty())
+    .collect()
+}
+
+pub(super) fn on_path(program: &str) -> bool {
+  if program.contains('/') {
+    return Path::new(program).is_file();
+  }
+
+  env::var_os("PATH")
+    .map(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests;
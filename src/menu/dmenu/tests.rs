@@ -0,0 +1,41 @@
+use super::{chosen, input, parse_index, Dmenu, Entry, Rofi};
+
+/// two items whose previews cannot be told apart
+fn entries() -> Vec<Entry> {
+  [3, 7]
+    .into_iter()
+    .map(|index| Entry {
+      index,
+      key: format!("key {}", index),
+      line: "same preview".to_string(),
+      icon: None,
+    })
+    .collect()
+}
+
+#[test]
+fn launchers_printing_positions_get_plain_lines() {
+  assert_eq!(input::<Rofi>(&entries()), "same preview\nsame preview");
+
+  let entry = chosen::<Rofi>(entries(), "1\n").unwrap();
+  assert_eq!(entry.key, "key 7");
+  assert!(chosen::<Rofi>(entries(), "2").is_none());
+  assert!(chosen::<Rofi>(entries(), "-1").is_none());
+}
+
+#[test]
+fn other_launchers_get_lines_starting_with_the_index() {
+  assert_eq!(input::<Dmenu>(&entries()), "3\tsame preview\n7\tsame preview");
+
+  let entry = chosen::<Dmenu>(entries(), "7\tsame preview").unwrap();
+  assert_eq!(entry.key, "key 7");
+  assert!(chosen::<Dmenu>(entries(), "same preview").is_none());
+  assert!(chosen::<Dmenu>(entries(), "4\tsame preview").is_none());
+}
+
+#[test]
+fn index_is_read_before_the_first_tab() {
+  assert_eq!(parse_index("12\ta\tb"), Some(12));
+  assert_eq!(parse_index(" 5 "), Some(5));
+  assert_eq!(parse_index("five\t5"), None);
+}
@@ -1,20 +1,51 @@
+mod action;
 mod bemenu;
+mod custom;
+mod dmenu;
+pub mod preview;
 
 use crate::{clipboard, config::error::Error};
 
-pub trait Menu {
+pub use self::action::{edit, Action};
+use self::dmenu::DMenu;
+
+pub trait Menu: Send {
   fn new(clipboard: clipboard::WrappedClipboard) -> Result<Box<Self>, Error>
   where
     Self: Sized;
-  fn show(&self) -> Result<Option<(String, usize)>, Error>;
+  /// let the user pick from the history items matching `query` (all of them when empty), returning
+  /// what to do and the chosen item. what gets pasted is always looked up from the history
+  fn show(&self, query: &str) -> Result<Option<(Action, Chosen)>, Error>;
+}
+
+/// a history item picked from a menu
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chosen {
+  /// menu index of the item when the menu was shown
+  pub index: usize,
+  /// `Item::key` of the item, to find it again if something was copied while the menu was open
+  pub key: String,
 }
 
 pub type WrappedMenu = Box<dyn Menu>;
 
+/// the values `general.menu` may take
+pub const MENUS: &[&str] = &["bemenu", "fuzzel", "wofi", "rofi", "tofi", "dmenu", "custom"];
+
+/// start the menu backend named by `general.menu`
 pub fn init(clipboard: clipboard::WrappedClipboard) -> Result<WrappedMenu, Error> {
-  if let Ok(menu) = bemenu::BeMenu::new(clipboard) {
-    Ok(menu)
-  } else {
-    Err(Error::NoMenu)
-  }
+  let name = clipboard.read().unwrap().get_config().general.menu;
+
+  let menu: WrappedMenu = match name.as_str() {
+    "bemenu" => bemenu::BeMenu::new(clipboard)?,
+    "fuzzel" => DMenu::<dmenu::Fuzzel>::new(clipboard)?,
+    "wofi" => DMenu::<dmenu::Wofi>::new(clipboard)?,
+    "rofi" => DMenu::<dmenu::Rofi>::new(clipboard)?,
+    "tofi" => DMenu::<dmenu::Tofi>::new(clipboard)?,
+    "dmenu" => DMenu::<dmenu::Dmenu>::new(clipboard)?,
+    "custom" => custom::Custom::new(clipboard)?,
+    _ => return Err(Error::NoMenu(name)),
+  };
+
+  Ok(menu)
 }
@@ -0,0 +1,80 @@
+//! one line summaries of history items for menus. only ever used for display - whatever gets
+//! pasted comes from the stored item.
+
+use crate::config::data::PreviewConfig;
+
+pub struct Preview {
+  width: usize,
+  newline: String,
+}
+
+impl Preview {
+  pub fn new(config: &PreviewConfig) -> Self {
+    Preview {
+      width: config.width,
+      newline: config.newline.clone(),
+    }
+  }
+
+  /// fold `text` onto one line, cut it to the configured width and note how much is hidden
+  pub fn text(&self, text: &str) -> String {
+    let lines = text.trim().lines().count();
+
+    let folded = (text.trim().lines())
+      .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+      .filter(|line| !line.is_empty())
+      .collect::<Vec<_>>()
+      .join(&format!(" {} ", self.newline));
+
+    // control characters (interior NULs included) would garble the menu or get the entry dropped
+    let folded = folded.chars().filter(|c| !c.is_control()).collect::<String>();
+
+    let (shown, truncated) = self.truncate(&folded);
+
+    match (lines, truncated) {
+      (0 | 1, false) => shown,
+      (0 | 1, true) => format!("{} ({})", shown, human_size(text.len())),
+      (lines, _) => format!("{} ({} lines)", shown, lines),
+    }
+  }
+
+  /// describe an image, e.g. `[image/png 1920x1080 340.2 KiB] 2024-06-01 14:05`
+  pub fn image(&self, mime: &str, dimensions: Option<(usize, usize)>, size: usize, copied_at: Option<u64>) -> String {
+    let mut label = vec![mime.to_string()];
+    if let Some((width, height)) = dimensions {
+      label.push(format!("{}x{}", width, height));
+    }
+    label.push(human_size(size));
+
+    match copied_at.and_then(timestamp) {
+      Some(time) => format!("[{}] {}", label.join(" "), time),
+      None => format!("[{}]", label.join(" ")),
+    }
+  }
+
+  fn truncate(&self, text: &str) -> (String, bool) {
+    if self.width == 0 || text.chars().count() <= self.width {
+      return (text.to_string(), false);
+    }
+
+    let cut = text.chars().take(self.width.saturating_sub(1)).collect::<String>();
+    (format!("{}…", cut.trim_end()), true)
+  }
+}
+
+/// local time for a unix timestamp
+pub fn timestamp(secs: u64) -> Option<String> {
+  let time = chrono::DateTime::from_timestamp(secs.try_into().ok()?, 0)?;
+  Some(time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+}
+
+pub fn human_size(size: usize) -> String {
+  match size {
+    size if size >= 1024 * 1024 => format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0)),
+    size if size >= 1024 => format!("{:.1} KiB", size as f64 / 1024.0),
+    size => format!("{} B", size),
+  }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,32 @@
+use super::Preview;
+use crate::config::data::PreviewConfig;
+
+fn preview(width: usize) -> Preview {
+  Preview::new(&PreviewConfig {
+    width,
+    newline: "⏎".to_string(),
+  })
+}
+
+#[test]
+fn short_text_is_unchanged() {
+  assert_eq!(preview(80).text("hello world"), "hello world");
+}
+
+#[test]
+fn newlines_become_markers_with_line_count() {
+  assert_eq!(preview(80).text("fn main() {\n    run();\n}\n"), "fn main() { ⏎ run(); ⏎ } (3 lines)");
+}
+
+#[test]
+fn long_text_is_cut_with_size() {
+  let text = "a".repeat(2048);
+
+  assert_eq!(preview(8).text(&text), "aaaaaaa… (2.0 KiB)");
+  assert_eq!(preview(0).text(&text), text);
+}
+
+#[test]
+fn control_characters_are_dropped() {
+  assert_eq!(preview(80).text("nul\0in\x07side"), "nulinside");
+}
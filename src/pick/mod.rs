@@ -0,0 +1,341 @@
+//! `wayclip pick`: a terminal picker that talks to the running daemon over its socket. choosing an
+//! item hands its index back to the daemon, which copies and pastes it exactly like a menu selection.
+
+use std::{
+  borrow::Cow,
+  path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, RgbaImage};
+use ratatui::{
+  buffer::Buffer,
+  crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+  layout::{Constraint, Layout, Rect},
+  style::{Color, Modifier, Style},
+  text::Line,
+  widgets::{Block, List, ListItem, ListState, Paragraph, Widget, Wrap},
+  DefaultTerminal, Frame,
+};
+
+use crate::{
+  communication::{Entry, EntryContent, Request, Response, SocketHandler},
+  config::data::Config,
+  menu::{
+    preview::{human_size, timestamp, Preview},
+    Action,
+  },
+  search::{Candidate, Searcher},
+};
+
+/// what the user chose in the picker
+struct Choice {
+  index: usize,
+  key: String,
+  action: Action,
+}
+
+pub fn pick(seat: Option<String>, config: &Config) {
+  let mut picker = Picker::new(list(), config);
+
+  let mut terminal = ratatui::init();
+  let choice = picker.run(&mut terminal);
+  ratatui::restore();
+
+  match choice {
+    Ok(Some(Choice { index, key, action })) => {
+      if let Some(Response::Error(e)) = request(Request::Act {
+        index,
+        key,
+        action,
+        seat,
+      }) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      }
+    }
+    Ok(None) => {}
+    Err(e) => {
+      eprintln!("terminal error: {}", e);
+      std::process::exit(1);
+    }
+  }
+}
+
+fn request(request: Request) -> Option<Response> {
+  SocketHandler::client().request(request)
+}
+
+fn list() -> Vec<Entry> {
+  match request(Request::List) {
+    Some(Response::Entries(entries)) => entries,
+    _ => vec![],
+  }
+}
+
+struct Picker {
+  entries: Vec<Entry>,
+  query: String,
+  /// positions in `entries` that match the query, best first
+  matches: Vec<usize>,
+  state: ListState,
+  searcher: Searcher,
+  preview: Preview,
+  /// the last thumbnail shown, so it is not decoded again every frame
+  thumbnail: Option<(PathBuf, RgbaImage)>,
+}
+
+impl Picker {
+  fn new(entries: Vec<Entry>, config: &Config) -> Self {
+    let mut picker = Picker {
+      entries,
+      query: String::new(),
+      matches: vec![],
+      state: ListState::default(),
+      searcher: Searcher::new(config.search.mode),
+      preview: Preview::new(&config.preview),
+      thumbnail: None,
+    };
+
+    picker.filter();
+    picker
+  }
+
+  fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<Option<Choice>> {
+    loop {
+      terminal.draw(|frame| self.draw(frame))?;
+
+      let key = match event::read()? {
+        Event::Key(key) if key.kind == KeyEventKind::Press => key,
+        _ => continue,
+      };
+
+      if let Some(done) = self.handle_key(key) {
+        return Ok(done);
+      }
+    }
+  }
+
+  /// returns `Some` once the picker should close
+  fn handle_key(&mut self, key: KeyEvent) -> Option<Option<Choice>> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+      KeyCode::Esc => return Some(None),
+      KeyCode::Char('c') if ctrl => return Some(None),
+      KeyCode::Enter => return Some(self.choose(Action::Paste)),
+      KeyCode::Char('y') if ctrl => return Some(self.choose(Action::Copy)),
+      KeyCode::Char('e') if ctrl => return Some(self.choose(Action::Edit)),
+      KeyCode::Char('k') if ctrl => return Some(self.choose(Action::TypeOut)),
+      KeyCode::Up => self.state.select_previous(),
+      KeyCode::Char('p') if ctrl => self.state.select_previous(),
+      KeyCode::Down => self.state.select_next(),
+      KeyCode::Char('n') if ctrl => self.state.select_next(),
+      KeyCode::Char('t') if ctrl => self.act(Action::Pin),
+      KeyCode::Delete => self.act(Action::Delete),
+      KeyCode::Char('d') if ctrl => self.act(Action::Delete),
+      KeyCode::Backspace => {
+        self.query.pop();
+        self.filter();
+      }
+      KeyCode::Char(c) if !ctrl => {
+        self.query.push(c);
+        self.filter();
+      }
+      _ => {}
+    }
+
+    None
+  }
+
+  fn choose(&self, action: Action) -> Option<Choice> {
+    self.selected().map(|entry| Choice {
+      index: entry.index,
+      key: entry.key.clone(),
+      action,
+    })
+  }
+
+  /// an action that leaves the picker open, showing the history as it is afterwards
+  fn act(&mut self, action: Action) {
+    if let Some(entry) = self.selected() {
+      request(Request::Act {
+        index: entry.index,
+        key: entry.key.clone(),
+        action,
+        seat: None,
+      });
+      self.reload();
+    }
+  }
+
+  /// the highlighted entry
+  fn selected(&self) -> Option<&Entry> {
+    let position = *self.matches.get(self.state.selected()?)?;
+    self.entries.get(position)
+  }
+
+  fn reload(&mut self) {
+    self.entries = list();
+    self.filter();
+  }
+
+  fn filter(&mut self) {
+    // the daemon lists entries newest first, so a position ranks by recency the same as a menu index
+    let candidates = self.entries.iter().enumerate().map(|(position, entry)| Candidate {
+      index: position,
+      text: searchable(entry),
+      uses: entry.uses,
+    });
+    self.matches = self.searcher.search(&self.query, candidates);
+
+    let selected = match self.matches.len() {
+      0 => None,
+      len => Some(self.state.selected().unwrap_or(0).min(len - 1)),
+    };
+    self.state.select(selected);
+  }
+
+  fn draw(&mut self, frame: &mut Frame) {
+    let [search, body, help] = Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)])
+      .areas(frame.area());
+    let [list, preview] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(body);
+
+    let title = format!("search ({}/{})", self.matches.len(), self.entries.len());
+    let input = Paragraph::new(self.query.as_str()).block(Block::bordered().title(title));
+    frame.render_widget(input, search);
+
+    let items = self
+      .matches
+      .iter()
+      .map(|&position| ListItem::new(summary(&self.preview, &self.entries[position])))
+      .collect::<Vec<_>>();
+    let items = List::new(items)
+      .block(Block::bordered().title("history"))
+      .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(items, list, &mut self.state);
+
+    let block = Block::bordered().title("preview");
+    let inner = block.inner(preview);
+    frame.render_widget(block, preview);
+
+    let Some(entry) = self.selected() else {
+      return;
+    };
+
+    let details = details(entry);
+    let thumbnail = match &entry.content {
+      EntryContent::Image {
+        thumbnail: Some(path), ..
+      } => Some(path.clone()),
+      _ => None,
+    };
+    let thumbnail = thumbnail.and_then(|path| self.load_thumbnail(&path));
+
+    match thumbnail {
+      Some(thumbnail) => {
+        let lines = details.lines().count() as u16 + 1;
+        let [text, image] = Layout::vertical([Constraint::Length(lines), Constraint::Min(0)]).areas(inner);
+        frame.render_widget(Paragraph::new(details), text);
+        frame.render_widget(Thumbnail(thumbnail), image);
+      }
+      None => frame.render_widget(Paragraph::new(details).wrap(Wrap { trim: false }), inner),
+    }
+
+    let keys = "enter paste · ctrl-y copy · ctrl-k type · ctrl-e edit · ctrl-t pin · ctrl-d delete · esc quit";
+    frame.render_widget(Line::from(keys).style(Style::default().add_modifier(Modifier::DIM)), help);
+  }
+
+  fn load_thumbnail(&mut self, path: &Path) -> Option<&RgbaImage> {
+    if self.thumbnail.as_ref().map(|(loaded, _)| loaded.as_path()) != Some(path) {
+      let image = image::open(path).ok()?.to_rgba8();
+      self.thumbnail = Some((path.to_path_buf(), image));
+    }
+
+    self.thumbnail.as_ref().map(|(_, image)| image)
+  }
+}
+
+/// draws an image with half block characters, two pixels to a cell
+struct Thumbnail<'a>(&'a RgbaImage);
+
+impl Widget for Thumbnail<'_> {
+  fn render(self, area: Rect, buf: &mut Buffer) {
+    let (width, height) = (self.0.width(), self.0.height());
+    if width == 0 || height == 0 || area.is_empty() {
+      return;
+    }
+
+    // fit inside the area without stretching or growing the image
+    let scale = (area.width as f64 / width as f64)
+      .min(area.height as f64 * 2.0 / height as f64)
+      .min(1.0);
+    let (w, h) = (((width as f64 * scale) as u32).max(1), ((height as f64 * scale) as u32).max(1));
+    let scaled = image::imageops::resize(self.0, w, h, FilterType::Triangle);
+
+    for y in 0..h.div_ceil(2) {
+      for x in 0..w {
+        let top = color(scaled.get_pixel(x, y * 2));
+        let bottom = (y * 2 + 1 < h).then(|| color(scaled.get_pixel(x, y * 2 + 1)));
+
+        if let Some(cell) = buf.cell_mut((area.x + x as u16, area.y + y as u16)) {
+          cell.set_char('▀').set_fg(top).set_bg(bottom.unwrap_or(Color::Reset));
+        }
+      }
+    }
+  }
+}
+
+fn color(pixel: &image::Rgba<u8>) -> Color {
+  let [r, g, b, _] = pixel.0;
+  Color::Rgb(r, g, b)
+}
+
+fn searchable(entry: &Entry) -> Cow<'_, str> {
+  match &entry.content {
+    EntryContent::Text { preview, .. } => Cow::Borrowed(preview),
+    EntryContent::Image { mime, .. } => Cow::Owned(format!("image {}", mime)),
+  }
+}
+
+/// one line for the history list
+fn summary(preview: &Preview, entry: &Entry) -> String {
+  let pin = if entry.pinned { "* " } else { "" };
+
+  match &entry.content {
+    EntryContent::Text { preview: text, .. } => format!("{}{}", pin, preview.text(text)),
+    EntryContent::Image { mime, size, dimensions, .. } => {
+      format!("{}{}", pin, preview.image(mime, *dimensions, *size, entry.copied_at))
+    }
+  }
+}
+
+/// everything shown in the preview pane
+fn details(entry: &Entry) -> String {
+  let mut info = vec![];
+  if entry.pinned {
+    info.push("pinned".to_string());
+  }
+  if let Some(seat) = &entry.seat {
+    info.push(format!("seat {}", seat));
+  }
+  if let Some(time) = entry.copied_at.and_then(timestamp) {
+    info.push(format!("copied {}", time));
+  }
+
+  let body = match &entry.content {
+    EntryContent::Text { preview, size } if preview.len() < *size => {
+      format!("{}\n\n... {} more", preview, human_size(size - preview.len()))
+    }
+    EntryContent::Text { preview, .. } => preview.clone(),
+    EntryContent::Image { mime, size, dimensions, .. } => {
+      let dimensions = dimensions.map_or("unknown size".to_string(), |(w, h)| format!("{}x{}", w, h));
+      format!("image\n{}\n{}\n{} ({} bytes)", mime, dimensions, human_size(*size), size)
+    }
+  };
+
+  if info.is_empty() {
+    body
+  } else {
+    format!("[{}]\n\n{}", info.join(", "), body)
+  }
+}
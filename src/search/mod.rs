@@ -0,0 +1,128 @@
+//! searching the history without relying on whatever filtering a menu backend happens to have.
+//! results are ranked by how well they match, then nudged towards recent and often used items.
+
+use std::borrow::Cow;
+
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use serde::{Deserialize, Serialize};
+
+use crate::clipboard::{Item, ItemData};
+
+/// how much an item that is brand new is worth over the oldest one
+const RECENCY_WEIGHT: f64 = 20.0;
+/// bonus per doubling of the use count
+const USE_WEIGHT: f64 = 10.0;
+/// how far into a text matches are looked for, so a huge item does not stall every keystroke
+const HAYSTACK_CHARS: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+  /// characters of the query appear in order, not necessarily next to each other
+  #[default]
+  Fuzzy,
+  /// the query appears as is
+  Substring,
+}
+
+/// something to search, indexed the way menus index the history (newest first)
+pub struct Candidate<'a> {
+  pub index: usize,
+  pub text: Cow<'a, str>,
+  pub uses: u32,
+}
+
+impl<'a> Candidate<'a> {
+  pub fn from_item(index: usize, item: &'a Item) -> Self {
+    let text = match &item.data {
+      ItemData::Text(text) => Cow::Borrowed(text.text.as_str()),
+      ItemData::Image(image) => Cow::Owned(format!("image {}", image.mime)),
+    };
+
+    Candidate {
+      index,
+      text,
+      uses: item.uses,
+    }
+  }
+}
+
+pub struct Searcher {
+  mode: SearchMode,
+  matcher: SkimMatcherV2,
+}
+
+impl Searcher {
+  pub fn new(mode: SearchMode) -> Self {
+    Searcher {
+      mode,
+      matcher: SkimMatcherV2::default().smart_case(),
+    }
+  }
+
+  /// indexes of the candidates matching `query`, best first. an empty query matches everything and
+  /// keeps the order the candidates came in
+  pub fn search<'a>(&self, query: &str, candidates: impl IntoIterator<Item = Candidate<'a>>) -> Vec<usize> {
+    let candidates = candidates.into_iter().collect::<Vec<_>>();
+    if query.is_empty() {
+      return candidates.iter().map(|candidate| candidate.index).collect();
+    }
+
+    let oldest = candidates.iter().map(|candidate| candidate.index).max().unwrap_or(0) as f64;
+
+    let mut ranked = candidates
+      .iter()
+      .filter_map(|candidate| {
+        let quality = self.quality(&candidate.text, query)? as f64;
+        let recency = RECENCY_WEIGHT * (1.0 - candidate.index as f64 / oldest.max(1.0));
+        let uses = USE_WEIGHT * (candidate.uses as f64 + 1.0).log2();
+
+        Some((quality + recency + uses, candidate.index))
+      })
+      .collect::<Vec<_>>();
+
+    // stable, so ties stay in the order they came in
+    ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    ranked.into_iter().map(|(_, index)| index).collect()
+  }
+
+  /// how well the start of `text` matches, or `None` if it does not
+  fn quality(&self, text: &str, query: &str) -> Option<i64> {
+    let text = match text.char_indices().nth(HAYSTACK_CHARS) {
+      Some((end, _)) => &text[..end],
+      None => text,
+    };
+
+    match self.mode {
+      SearchMode::Fuzzy => self.matcher.fuzzy_match(text, query),
+      SearchMode::Substring => substring_quality(text, query),
+    }
+  }
+}
+
+/// smart case like the fuzzy matcher: an uppercase letter in the query makes it case sensitive.
+/// matches at the start of the text beat matches at the start of a word, which beat the rest
+fn substring_quality(text: &str, query: &str) -> Option<i64> {
+  let (text, query) = if query.chars().any(char::is_uppercase) {
+    (Cow::Borrowed(text), Cow::Borrowed(query))
+  } else {
+    (Cow::Owned(text.to_lowercase()), Cow::Owned(query.to_lowercase()))
+  };
+
+  let at = text.find(query.as_ref())?;
+  let word_start = text[..at].chars().next_back().map_or(true, |c| !c.is_alphanumeric());
+
+  let placement = match (at, word_start) {
+    (0, _) => 100,
+    (_, true) => 60,
+    _ => 30,
+  };
+
+  // shorter texts are a closer match for the same query
+  let closeness = (50 * query.len() / text.len().max(1)) as i64;
+
+  Some(placement + closeness)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+use super::{Candidate, SearchMode, Searcher};
+
+fn candidates<'a>(texts: &[&'a str]) -> Vec<Candidate<'a>> {
+  (texts.iter().enumerate())
+    .map(|(index, text)| Candidate {
+      index,
+      text: Cow::Borrowed(*text),
+      uses: 0,
+    })
+    .collect()
+}
+
+#[test]
+fn empty_query_keeps_order() {
+  let searcher = Searcher::new(SearchMode::Fuzzy);
+
+  assert_eq!(searcher.search("", candidates(&["b", "a", "c"])), vec![0, 1, 2]);
+}
+
+#[test]
+fn fuzzy_matches_scattered_characters() {
+  let searcher = Searcher::new(SearchMode::Fuzzy);
+
+  assert_eq!(searcher.search("gco", candidates(&["git checkout", "ls -la"])), vec![0]);
+}
+
+#[test]
+fn substring_needs_contiguous_match() {
+  let searcher = Searcher::new(SearchMode::Substring);
+
+  assert_eq!(searcher.search("gco", candidates(&["git checkout"])), Vec::<usize>::new());
+  assert_eq!(searcher.search("check", candidates(&["git checkout"])), vec![0]);
+}
+
+#[test]
+fn substring_is_smart_case() {
+  let searcher = Searcher::new(SearchMode::Substring);
+  let texts = ["Hello", "hello"];
+
+  assert_eq!(searcher.search("hello", candidates(&texts)).len(), 2);
+  assert_eq!(searcher.search("Hello", candidates(&texts)), vec![0]);
+}
+
+#[test]
+fn better_matches_rank_first() {
+  let searcher = Searcher::new(SearchMode::Substring);
+
+  // the prefix match wins even though it is older
+  assert_eq!(searcher.search("cargo", candidates(&["run cargo test", "cargo test"])), vec![1, 0]);
+}
+
+#[test]
+fn recency_and_uses_break_ties() {
+  let searcher = Searcher::new(SearchMode::Fuzzy);
+
+  assert_eq!(searcher.search("token", candidates(&["token", "token"])), vec![0, 1]);
+
+  let mut used = candidates(&["token", "token"]);
+  used[1].uses = 8;
+  assert_eq!(searcher.search("token", used), vec![1, 0]);
+}
+
+#[test]
+fn only_the_start_of_long_texts_is_searched() {
+  let long = format!("{}needle", "x".repeat(super::HAYSTACK_CHARS));
+  let texts = ["needle in front", long.as_str()];
+
+  for mode in [SearchMode::Fuzzy, SearchMode::Substring] {
+    assert_eq!(Searcher::new(mode).search("needle", candidates(&texts)), vec![0]);
+  }
+}
@@ -0,0 +1,105 @@
+//! stops the daemon cleanly on SIGTERM, SIGINT or `wayclip quit`, so a systemd stop never kills it
+//! in the middle of writing the history. a second signal kills it the usual way.
+
+use std::{
+  fs::File,
+  io::Read,
+  os::fd::FromRawFd,
+  sync::{
+    atomic::{AtomicI32, Ordering},
+    Arc, Condvar, Mutex,
+  },
+  time::Duration,
+};
+
+use tracing::{info, warn};
+
+/// write end of the pipe the signal handler wakes the watching thread through
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// set once the daemon should stop, shared by every thread that has to notice
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown(Arc<(Mutex<bool>, Condvar)>);
+
+impl Shutdown {
+  pub fn request(&self) {
+    let (requested, changed) = &*self.0;
+    *requested.lock().unwrap() = true;
+    changed.notify_all();
+  }
+
+  pub fn requested(&self) -> bool {
+    *self.0 .0.lock().unwrap()
+  }
+
+  /// block until a shutdown is requested
+  pub fn wait(&self) {
+    let (requested, changed) = &*self.0;
+    let _requested = changed.wait_while(requested.lock().unwrap(), |requested| !*requested).unwrap();
+  }
+
+  /// block until a shutdown is requested or `timeout` passes, returning whether one was
+  pub fn wait_timeout(&self, timeout: Duration) -> bool {
+    let (requested, changed) = &*self.0;
+    let (requested, _) = (changed)
+      .wait_timeout_while(requested.lock().unwrap(), timeout, |requested| !*requested)
+      .unwrap();
+
+    *requested
+  }
+}
+
+/// only what is async-signal-safe: one write to a pipe
+extern "C" fn on_signal(_: libc::c_int) {
+  let fd = SIGNAL_PIPE.load(Ordering::Relaxed);
+  if fd >= 0 {
+    unsafe { libc::write(fd, [1u8].as_ptr().cast(), 1) };
+  }
+}
+
+/// request `shutdown` on the first SIGINT or SIGTERM. the handler is reset once it runs, so the
+/// next one stops wayclip right away
+pub fn on_signals(shutdown: Shutdown) {
+  if !watch_signal_pipe(shutdown) {
+    return;
+  }
+
+  for signal in [libc::SIGINT, libc::SIGTERM] {
+    let installed = unsafe {
+      let mut action: libc::sigaction = std::mem::zeroed();
+      action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+      action.sa_flags = libc::SA_RESTART | libc::SA_RESETHAND;
+      libc::sigemptyset(&mut action.sa_mask);
+
+      libc::sigaction(signal, &action, std::ptr::null_mut())
+    };
+
+    if installed < 0 {
+      warn!("cannot handle signal {}: {}", signal, std::io::Error::last_os_error());
+    }
+  }
+}
+
+/// open the pipe `on_signal` writes to and request `shutdown` once something arrives on it
+fn watch_signal_pipe(shutdown: Shutdown) -> bool {
+  let mut fds = [0; 2];
+  if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+    warn!("cannot handle signals: {}", std::io::Error::last_os_error());
+    return false;
+  }
+  SIGNAL_PIPE.store(fds[1], Ordering::Relaxed);
+  let mut signals = unsafe { File::from_raw_fd(fds[0]) };
+
+  std::thread::spawn(move || {
+    let mut signal = [0u8; 1];
+    if signals.read_exact(&mut signal).is_ok() {
+      info!("received a signal, shutting down");
+      shutdown.request();
+    }
+  });
+
+  true
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,66 @@
+use std::{sync::mpsc, time::Duration};
+
+use super::{on_signal, watch_signal_pipe, Shutdown};
+use crate::{
+  clipboard::{
+    capture::Origin,
+    fixtures::{self, text},
+  },
+  config::data::Config,
+};
+
+/// whether `shutdown` is requested within a second
+fn stops(shutdown: &Shutdown) -> bool {
+  let (tx, rx) = mpsc::channel();
+  let waiting = shutdown.clone();
+  std::thread::spawn(move || {
+    waiting.wait();
+    let _ = tx.send(());
+  });
+
+  rx.recv_timeout(Duration::from_secs(1)).is_ok()
+}
+
+#[test]
+fn request_wakes_every_waiter() {
+  let shutdown = Shutdown::default();
+  assert!(!shutdown.requested());
+  assert!(!shutdown.wait_timeout(Duration::from_millis(10)));
+
+  let requester = shutdown.clone();
+  std::thread::spawn(move || {
+    std::thread::sleep(Duration::from_millis(50));
+    requester.request();
+  });
+
+  assert!(stops(&shutdown));
+  assert!(stops(&shutdown));
+  assert!(shutdown.requested());
+  assert!(shutdown.wait_timeout(Duration::from_secs(1)));
+}
+
+#[test]
+fn the_signal_handler_requests_a_shutdown() {
+  let shutdown = Shutdown::default();
+  assert!(watch_signal_pipe(shutdown.clone()));
+
+  // called directly, since raising a real signal would reach the whole test harness
+  on_signal(libc::SIGTERM);
+
+  assert!(stops(&shutdown));
+}
+
+#[test]
+fn closing_saves_and_then_records_nothing() {
+  let dir = tempfile::tempdir().unwrap();
+  let (clipboard, helper) = fixtures::clipboard(dir.path(), Config::default());
+  let mut borrow = clipboard.write().unwrap();
+  borrow.commit(text("before"), &Origin::default());
+  borrow.close();
+  borrow.commit(text("after"), &Origin::default());
+
+  let saved = helper.retrieve_clipboard().unwrap();
+  assert_eq!(saved.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec!["before"]);
+  assert!(borrow.hist.iter().all(|item| item.id != "after"));
+  assert!(!dir.path().join("cache/clipboard.bin.tmp").exists());
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+use wayland_client::{ConnectError, DispatchError};
+
+#[derive(Debug)]
+pub enum Error {
+  Connect(ConnectError),
+  Dispatch(DispatchError),
+  MissingProtocol(&'static str),
+  NoSeat,
+}
+
+impl Error {
+  /// errors that reconnecting cannot fix
+  pub fn is_fatal(&self) -> bool {
+    matches!(self, Error::MissingProtocol(_))
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Connect(e) => write!(f, "could not connect to the wayland compositor: {}", e),
+      Error::Dispatch(e) => write!(f, "lost connection to the wayland compositor: {}", e),
+      Error::MissingProtocol(protocol) => write!(
+        f,
+        "the compositor does not support {}, which wayclip needs to watch the clipboard",
+        protocol
+      ),
+      Error::NoSeat => write!(f, "the compositor did not advertise a seat"),
+    }
+  }
+}
+
+impl From<ConnectError> for Error {
+  fn from(e: ConnectError) -> Self {
+    Error::Connect(e)
+  }
+}
+
+impl From<DispatchError> for Error {
+  fn from(e: DispatchError) -> Self {
+    Error::Dispatch(e)
+  }
+}
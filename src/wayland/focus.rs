@@ -0,0 +1,134 @@
+//! keeps track of which application has focus through zwlr_foreign_toplevel_management_v1, so the
+//! paste chord can be picked per application. compositors without the protocol simply never report
+//! a focused application.
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use tracing::trace;
+use wayland_client::{backend::ObjectId, event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+  zwlr_foreign_toplevel_handle_v1::{self, State, ZwlrForeignToplevelHandleV1},
+  zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use super::WaylandState;
+
+pub const TOPLEVEL_MANAGER: &str = "zwlr_foreign_toplevel_manager_v1";
+/// highest version we understand, v3 only added the parent event
+pub const TOPLEVEL_VERSION: u32 = 3;
+
+/// the app id of the focused window, shared with the thread that pastes
+#[derive(Clone, Debug, Default)]
+pub struct Focus(Arc<Mutex<Option<String>>>);
+
+impl Focus {
+  pub fn app_id(&self) -> Option<String> {
+    self.0.lock().unwrap().clone()
+  }
+
+  fn set(&self, app_id: Option<String>) {
+    *self.0.lock().unwrap() = app_id;
+  }
+}
+
+/// a window as last described by the compositor. changes only count once `done` arrives
+#[derive(Clone, Debug, Default)]
+pub struct Toplevel {
+  app_id: Option<String>,
+  activated: bool,
+  pending: PendingToplevel,
+}
+
+#[derive(Clone, Debug, Default)]
+struct PendingToplevel {
+  app_id: Option<String>,
+  activated: Option<bool>,
+}
+
+pub type Toplevels = HashMap<ObjectId, Toplevel>;
+
+impl WaylandState {
+  /// point `focus` at whichever window is activated now
+  fn update_focus(&self) {
+    let focused = self.toplevels.values().find(|toplevel| toplevel.activated);
+    let app_id = focused.and_then(|toplevel| toplevel.app_id.clone());
+
+    if self.focus.app_id() != app_id {
+      trace!("focus moved to {:?}", app_id);
+      self.focus.set(app_id);
+    }
+  }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WaylandState {
+  fn event(
+    state: &mut Self,
+    _: &ZwlrForeignToplevelManagerV1,
+    event: zwlr_foreign_toplevel_manager_v1::Event,
+    _: &(),
+    _: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+    match event {
+      zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+        state.toplevels.insert(toplevel.id(), Toplevel::default());
+      }
+      zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+        state.toplevels.clear();
+        state.update_focus();
+      }
+      _ => {}
+    }
+  }
+
+  event_created_child!(
+    WaylandState,
+    ZwlrForeignToplevelManagerV1,
+    [zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())]
+  );
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandState {
+  fn event(
+    state: &mut Self,
+    handle: &ZwlrForeignToplevelHandleV1,
+    event: zwlr_foreign_toplevel_handle_v1::Event,
+    _: &(),
+    _: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+    let Some(toplevel) = state.toplevels.get_mut(&handle.id()) else {
+      return;
+    };
+
+    match event {
+      zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.pending.app_id = Some(app_id),
+      zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+        // an array of native endian u32s
+        let activated = (states.chunks_exact(4))
+          .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+          .any(|value| value == State::Activated as u32);
+        toplevel.pending.activated = Some(activated);
+      }
+      zwlr_foreign_toplevel_handle_v1::Event::Done => {
+        let pending = std::mem::take(&mut toplevel.pending);
+        if let Some(app_id) = pending.app_id {
+          toplevel.app_id = Some(app_id);
+        }
+        if let Some(activated) = pending.activated {
+          toplevel.activated = activated;
+        }
+        state.update_focus();
+      }
+      zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+        state.toplevels.remove(&handle.id());
+        handle.destroy();
+        state.update_focus();
+      }
+      _ => {}
+    }
+  }
+}
@@ -0,0 +1,381 @@
+//! a headless compositor for tests. it speaks wl_seat and ext-data-control to a single client over a
+//! socketpair, and plays the part of whichever application owns the selection.
+
+use std::{
+  fs::File,
+  io::Write,
+  os::{fd::AsRawFd, unix::net::UnixStream},
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    mpsc, Arc,
+  },
+  thread::JoinHandle,
+  time::{Duration, Instant},
+};
+
+use wayland_protocols::ext::data_control::v1::server::{
+  ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+  ext_data_control_manager_v1::{self, ExtDataControlManagerV1},
+  ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+  ext_data_control_source_v1::{self, ExtDataControlSourceV1},
+};
+use wayland_server::{
+  backend::ClientId,
+  protocol::wl_seat::{self, WlSeat},
+  Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+/// what an application offers, one payload per mime type
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+  mimes: Vec<(String, Vec<u8>)>,
+  stall: bool,
+}
+
+impl Selection {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with(mut self, mime: &str, data: impl Into<Vec<u8>>) -> Self {
+    self.mimes.push((mime.to_string(), data.into()));
+    self
+  }
+
+  /// keep every pipe open without writing to it, like a hung source
+  pub fn stalled(mut self) -> Self {
+    self.stall = true;
+    self
+  }
+}
+
+/// a selection to announce, on one seat or on all of them
+struct SetSelection {
+  seat: Option<String>,
+  selection: Selection,
+  primary: bool,
+}
+
+enum Command {
+  SetSelection(SetSelection),
+  /// send `finished` to every data device, as compositors do when they drop them
+  Finish,
+}
+
+pub struct MockCompositor {
+  commands: mpsc::Sender<Command>,
+  devices: Arc<AtomicUsize>,
+  /// offers sent and not destroyed by the client yet
+  offers: Arc<AtomicUsize>,
+  stop: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl MockCompositor {
+  /// start a compositor with the given seats, returning it and the client end of its socket
+  pub fn start(seats: &[&str], data_control: bool) -> (Self, UnixStream) {
+    let (server, client) = UnixStream::pair().unwrap();
+
+    let mut display = Display::<MockState>::new().unwrap();
+    let dh = display.handle();
+
+    for seat in seats {
+      dh.create_global::<MockState, WlSeat, String>(2, seat.to_string());
+    }
+
+    if data_control {
+      dh.create_global::<MockState, ExtDataControlManagerV1, ()>(1, ());
+    }
+
+    display.handle().insert_client(server, Arc::new(())).unwrap();
+
+    let (commands, receiver) = mpsc::channel();
+    let devices = Arc::new(AtomicUsize::new(0));
+    let offers = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let mut state = MockState {
+      devices: vec![],
+      device_count: devices.clone(),
+      offer_count: offers.clone(),
+      stalled: vec![],
+    };
+
+    let t_stop = stop.clone();
+    let thread = std::thread::spawn(move || {
+      while !t_stop.load(Ordering::SeqCst) {
+        while let Ok(command) = receiver.try_recv() {
+          match command {
+            Command::SetSelection(command) => state.set_selection(&dh, command),
+            Command::Finish => state.finish(),
+          }
+        }
+
+        let _ = display.dispatch_clients(&mut state);
+        let _ = display.flush_clients();
+
+        let mut fd = libc::pollfd {
+          fd: display.backend().poll_fd().as_raw_fd(),
+          events: libc::POLLIN,
+          revents: 0,
+        };
+        unsafe { libc::poll(&mut fd, 1, 5) };
+      }
+    });
+
+    let compositor = MockCompositor {
+      commands,
+      devices,
+      offers,
+      stop,
+      thread: Some(thread),
+    };
+
+    (compositor, client)
+  }
+
+  pub fn set_selection(&self, selection: Selection) {
+    self.send(None, selection, false);
+  }
+
+  pub fn set_selection_on(&self, seat: &str, selection: Selection) {
+    self.send(Some(seat.to_string()), selection, false);
+  }
+
+  pub fn set_primary_selection(&self, selection: Selection) {
+    self.send(None, selection, true);
+  }
+
+  pub fn finish_devices(&self) {
+    self.commands.send(Command::Finish).unwrap();
+  }
+
+  /// shut down and close the socket, as if the compositor had crashed
+  pub fn disconnect(&mut self) {
+    self.stop.store(true, Ordering::SeqCst);
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+
+  /// block until the client has destroyed every offer it was sent
+  pub fn wait_for_offers_destroyed(&self) {
+    assert!(
+      wait_for(|| self.offers.load(Ordering::SeqCst) == 0),
+      "client kept {} offers",
+      self.offers.load(Ordering::SeqCst)
+    );
+  }
+
+  /// block until the client has created `count` data devices
+  pub fn wait_for_devices(&self, count: usize) {
+    assert!(
+      wait_for(|| self.devices.load(Ordering::SeqCst) >= count),
+      "client never created {} data devices",
+      count
+    );
+  }
+
+  fn send(&self, seat: Option<String>, selection: Selection, primary: bool) {
+    let command = SetSelection {
+      seat,
+      selection,
+      primary,
+    };
+
+    self.commands.send(Command::SetSelection(command)).unwrap();
+  }
+}
+
+impl Drop for MockCompositor {
+  fn drop(&mut self) {
+    self.disconnect();
+  }
+}
+
+/// poll a condition for a few seconds, since capture happens on other threads
+pub fn wait_for(mut condition: impl FnMut() -> bool) -> bool {
+  let deadline = Instant::now() + Duration::from_secs(5);
+
+  while Instant::now() < deadline {
+    if condition() {
+      return true;
+    }
+
+    std::thread::sleep(Duration::from_millis(10));
+  }
+
+  condition()
+}
+
+struct MockState {
+  /// data devices with the name of the seat they were created for
+  devices: Vec<(String, ExtDataControlDeviceV1)>,
+  device_count: Arc<AtomicUsize>,
+  offer_count: Arc<AtomicUsize>,
+  /// write ends of pipes belonging to stalled selections
+  stalled: Vec<File>,
+}
+
+impl MockState {
+  fn finish(&mut self) {
+    for (_, device) in self.devices.drain(..) {
+      device.finished();
+    }
+  }
+
+  fn set_selection(&mut self, dh: &DisplayHandle, command: SetSelection) {
+    let selection = Arc::new(command.selection);
+    let devices = self
+      .devices
+      .iter()
+      .filter(|(name, _)| command.seat.as_ref().map_or(true, |seat| seat == name));
+
+    for (_, device) in devices {
+      let client = dh.get_client(device.id()).unwrap();
+      let offer = client
+        .create_resource::<ExtDataControlOfferV1, _, MockState>(dh, device.version(), selection.clone())
+        .unwrap();
+      self.offer_count.fetch_add(1, Ordering::SeqCst);
+
+      device.data_offer(&offer);
+      for (mime, _) in &selection.mimes {
+        offer.offer(mime.clone());
+      }
+
+      if command.primary {
+        device.primary_selection(Some(&offer));
+      } else {
+        device.selection(Some(&offer));
+      }
+    }
+  }
+}
+
+impl GlobalDispatch<WlSeat, String> for MockState {
+  fn bind(
+    _: &mut Self,
+    _: &DisplayHandle,
+    _: &Client,
+    resource: New<WlSeat>,
+    name: &String,
+    data_init: &mut DataInit<'_, Self>,
+  ) {
+    let seat = data_init.init(resource, name.clone());
+    seat.capabilities(wl_seat::Capability::Keyboard);
+    if seat.version() >= 2 {
+      seat.name(name.clone());
+    }
+  }
+}
+
+impl Dispatch<WlSeat, String> for MockState {
+  fn request(
+    _: &mut Self,
+    _: &Client,
+    _: &WlSeat,
+    _: wl_seat::Request,
+    _: &String,
+    _: &DisplayHandle,
+    _: &mut DataInit<'_, Self>,
+  ) {
+  }
+}
+
+impl GlobalDispatch<ExtDataControlManagerV1, ()> for MockState {
+  fn bind(
+    _: &mut Self,
+    _: &DisplayHandle,
+    _: &Client,
+    resource: New<ExtDataControlManagerV1>,
+    _: &(),
+    data_init: &mut DataInit<'_, Self>,
+  ) {
+    data_init.init(resource, ());
+  }
+}
+
+impl Dispatch<ExtDataControlManagerV1, ()> for MockState {
+  fn request(
+    state: &mut Self,
+    _: &Client,
+    _: &ExtDataControlManagerV1,
+    request: ext_data_control_manager_v1::Request,
+    _: &(),
+    _: &DisplayHandle,
+    data_init: &mut DataInit<'_, Self>,
+  ) {
+    match request {
+      ext_data_control_manager_v1::Request::GetDataDevice { id, seat } => {
+        let name = seat.data::<String>().cloned().unwrap_or_default();
+        let device = data_init.init(id, ());
+
+        state.devices.push((name, device));
+        state.device_count.fetch_add(1, Ordering::SeqCst);
+      }
+      ext_data_control_manager_v1::Request::CreateDataSource { id } => {
+        data_init.init(id, ());
+      }
+      _ => {}
+    }
+  }
+}
+
+impl Dispatch<ExtDataControlDeviceV1, ()> for MockState {
+  fn request(
+    _: &mut Self,
+    _: &Client,
+    _: &ExtDataControlDeviceV1,
+    _: ext_data_control_device_v1::Request,
+    _: &(),
+    _: &DisplayHandle,
+    _: &mut DataInit<'_, Self>,
+  ) {
+  }
+
+  fn destroyed(state: &mut Self, _: ClientId, device: &ExtDataControlDeviceV1, _: &()) {
+    state.devices.retain(|(_, existing)| existing != device);
+  }
+}
+
+impl Dispatch<ExtDataControlOfferV1, Arc<Selection>> for MockState {
+  fn request(
+    state: &mut Self,
+    _: &Client,
+    _: &ExtDataControlOfferV1,
+    request: ext_data_control_offer_v1::Request,
+    selection: &Arc<Selection>,
+    _: &DisplayHandle,
+    _: &mut DataInit<'_, Self>,
+  ) {
+    if let ext_data_control_offer_v1::Request::Receive { mime_type, fd } = request {
+      let mut pipe = File::from(fd);
+
+      if selection.stall {
+        state.stalled.push(pipe);
+        return;
+      }
+
+      if let Some((_, data)) = selection.mimes.iter().find(|(mime, _)| *mime == mime_type) {
+        let _ = pipe.write_all(data);
+      }
+    }
+  }
+
+  fn destroyed(state: &mut Self, _: ClientId, _: &ExtDataControlOfferV1, _: &Arc<Selection>) {
+    state.offer_count.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl Dispatch<ExtDataControlSourceV1, ()> for MockState {
+  fn request(
+    _: &mut Self,
+    _: &Client,
+    _: &ExtDataControlSourceV1,
+    _: ext_data_control_source_v1::Request,
+    _: &(),
+    _: &DisplayHandle,
+    _: &mut DataInit<'_, Self>,
+  ) {
+  }
+}
@@ -1,36 +1,112 @@
+mod error;
+mod focus;
+#[cfg(test)]
+mod mock;
+mod protocol;
+mod read;
+mod signal;
+#[cfg(test)]
+mod tests;
+
 use std::{
-  io::Read,
-  os::{fd::BorrowedFd, unix::io::AsRawFd},
+  collections::HashMap,
+  io,
+  os::fd::{AsFd, AsRawFd},
+  time::{Duration, Instant},
 };
 
-use tracing::{debug, trace};
+use tracing::{debug, error, trace, warn};
 use wayland_client::{
   event_created_child,
   protocol::{
     wl_registry,
     wl_seat::{self, WlSeat},
   },
-  Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+  backend::WaylandError,
+  Connection, Dispatch, DispatchError, EventQueue, Proxy, QueueHandle,
 };
 
 use wayland_protocols::ext::data_control::v1::client::{
   ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
   ext_data_control_manager_v1::ExtDataControlManagerV1,
-  ext_data_control_offer_v1::ExtDataControlOfferV1,
+  ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+};
+use wayland_protocols_wlr::{
+  data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+  },
+  foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
 };
 
 use crate::{
-  clipboard::{self, WrappedClipboard},
+  clipboard::{
+    self,
+    capture::{Capture, Origin},
+    WrappedClipboard,
+  },
   communication, input,
+  menu::Action,
+  shutdown::Shutdown,
 };
 
+pub use self::protocol::Offer;
+use self::{
+  error::Error,
+  focus::{Focus, Toplevels},
+  protocol::{Device, DeviceEvent, Manager},
+  read::{read_pipe, Generation, Generations, ReadError, ReadLimits, Reads},
+  signal::Selections,
+};
+
+/// exit code when the compositor lacks a required protocol, so systemd does not restart us forever
+const EXIT_UNSUPPORTED: i32 = 3;
+
+/// the wl_seat version that introduced the name event
+const SEAT_VERSION: u32 = 2;
+
+/// how long the event loop waits for events before checking whether wayclip is stopping
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// how long stopping waits for selection reads that are still running
+const READS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+struct Seat {
+  seat: WlSeat,
+  name: Option<String>,
+  device: Option<Device>,
+  generations: Generations,
+  /// delay before making again a device the compositor finished, growing while it keeps doing so
+  backoff: Backoff,
+  /// when the finished device is made again
+  recreate_at: Option<Instant>,
+}
+
 #[derive(Clone, Debug)]
 struct WaylandState {
   clipboard: WrappedClipboard,
 
-  seat: Option<WlSeat>,
-  manager: Option<ExtDataControlManagerV1>,
-  device: Option<ExtDataControlDeviceV1>,
+  /// every seat the compositor advertises, keyed by global name
+  seats: HashMap<u32, Seat>,
+  manager: Option<Manager>,
+  /// windows the compositor told us about, to know which one has focus
+  toplevels: Toplevels,
+  focus: Focus,
+  selections: Selections,
+  reads: Reads,
+  qh: QueueHandle<WaylandState>,
+}
+
+/// what the event loop finds out for the paste thread, and what it needs to stop, kept across
+/// reconnects
+#[derive(Clone, Debug, Default)]
+struct Watched {
+  focus: Focus,
+  selections: Selections,
+  reads: Reads,
+  shutdown: Shutdown,
 }
 
 impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
@@ -42,19 +118,58 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
     _: &Connection,
     qh: &QueueHandle<WaylandState>,
   ) {
+    if let wl_registry::Event::GlobalRemove { name } = event {
+      if let Some(seat) = state.seats.remove(&name) {
+        debug!("seat {:?} removed", seat.name);
+        seat.generations.next();
+        if let Some(device) = seat.device {
+          device.destroy();
+        }
+      }
+
+      return;
+    }
+
     if let wl_registry::Event::Global {
       name,
       interface,
-      version: _version,
+      version,
     } = event
     {
-      // println!("[{}] {} (v{})", name, interface, _version);
       match &interface[..] {
         "wl_seat" => {
-          registry.bind::<wl_seat::WlSeat, _, _>(name, 1, qh, ());
+          let seat = registry.bind::<wl_seat::WlSeat, _, _>(name, version.min(SEAT_VERSION), qh, name);
+          state.seats.insert(
+            name,
+            Seat {
+              seat,
+              name: None,
+              device: None,
+              generations: Generations::default(),
+              backoff: Backoff::new(),
+              recreate_at: None,
+            },
+          );
+
+          // seats that show up after startup need a device right away
+          state.create_devices();
+        }
+        protocol::EXT_MANAGER => {
+          // ext always wins, even if the wlr fallback was advertised first
+          if let Some(Manager::Wlr(manager)) = &state.manager {
+            manager.destroy();
+          }
+
+          let manager = registry.bind::<ExtDataControlManagerV1, _, _>(name, 1, qh, ());
+          state.manager = Some(Manager::Ext(manager));
         }
-        "ext_data_control_manager_v1" => {
-          state.manager = Some(registry.bind::<ExtDataControlManagerV1, _, _>(name, 1, qh, ()));
+        protocol::WLR_MANAGER if state.manager.is_none() => {
+          let version = version.min(protocol::WLR_VERSION);
+          let manager = registry.bind::<ZwlrDataControlManagerV1, _, _>(name, version, qh, ());
+          state.manager = Some(Manager::Wlr(manager));
+        }
+        focus::TOPLEVEL_MANAGER => {
+          registry.bind::<ZwlrForeignToplevelManagerV1, _, _>(name, version.min(focus::TOPLEVEL_VERSION), qh, ());
         }
         _ => {}
       }
@@ -62,13 +177,21 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
   }
 }
 
-impl Dispatch<wl_seat::WlSeat, ()> for WaylandState {
-  fn event(state: &mut Self, seat: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
-    if state.seat.is_some() {
-      return;
-    };
-
-    state.seat = Some(seat.clone());
+impl Dispatch<wl_seat::WlSeat, u32> for WaylandState {
+  fn event(
+    state: &mut Self,
+    _: &wl_seat::WlSeat,
+    event: wl_seat::Event,
+    global: &u32,
+    _: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+    if let wl_seat::Event::Name { name } = event {
+      debug!("seat {} is named {:?}", global, name);
+      if let Some(seat) = state.seats.get_mut(global) {
+        seat.name = Some(name);
+      }
+    }
   }
 }
 
@@ -84,45 +207,38 @@ impl Dispatch<ExtDataControlManagerV1, ()> for WaylandState {
   }
 }
 
-// impl Dispatch<ExtDataControlSourceV1, ()> for WaylandState {
-//   fn event(
-//     _: &mut Self,
-//     _: &ExtDataControlSourceV1,
-//     _: <ExtDataControlSourceV1 as Proxy>::Event,
-//     _: &(),
-//     _: &Connection,
-//     _: &QueueHandle<Self>,
-//   ) {
-//     panic!("source event");
-//   }
-// }
-
-impl Dispatch<ExtDataControlDeviceV1, ()> for WaylandState {
+impl Dispatch<ZwlrDataControlManagerV1, ()> for WaylandState {
+  fn event(
+    _: &mut Self,
+    _: &ZwlrDataControlManagerV1,
+    _: <ZwlrDataControlManagerV1 as Proxy>::Event,
+    _: &(),
+    _: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+  }
+}
+
+impl Dispatch<ExtDataControlDeviceV1, u32> for WaylandState {
   fn event(
     state: &mut Self,
     _: &ExtDataControlDeviceV1,
     event: <ExtDataControlDeviceV1 as Proxy>::Event,
-    _: &(),
+    global: &u32,
     conn: &Connection,
     _: &QueueHandle<Self>,
   ) {
-    match event {
-      ext_data_control_device_v1::Event::DataOffer { id } => {
-        let id = id.id();
-        trace!("data offer id: {:?}", id);
-        state.clipboard.write().unwrap().new_offer(id);
+    let event = match event {
+      ext_data_control_device_v1::Event::DataOffer { id } => DeviceEvent::DataOffer(Offer::Ext(id)),
+      ext_data_control_device_v1::Event::Selection { id } => DeviceEvent::Selection(id.map(Offer::Ext)),
+      ext_data_control_device_v1::Event::PrimarySelection { id } => {
+        DeviceEvent::PrimarySelection(id.map(Offer::Ext))
       }
+      ext_data_control_device_v1::Event::Finished => DeviceEvent::Finished,
+      _ => return,
+    };
 
-      ext_data_control_device_v1::Event::Selection { id } if id.is_some() => {
-        let id = id.unwrap().id();
-        trace!("selection id: {:?}", id);
-        let item = get_item(conn, state);
-        if let Some(item) = item {
-          state.clipboard.write().unwrap().commit(item);
-        }
-      }
-      _ => {}
-    }
+    state.handle_device_event(conn, *global, event);
   }
 
   event_created_child!(
@@ -132,33 +248,277 @@ impl Dispatch<ExtDataControlDeviceV1, ()> for WaylandState {
   );
 }
 
-fn get_item(conn: &Connection, state: &WaylandState) -> Option<clipboard::Item> {
-  let borrow = state.clipboard.read().unwrap();
-  let live = if let Some(live) = &borrow.live {
-    live
+impl Dispatch<ZwlrDataControlDeviceV1, u32> for WaylandState {
+  fn event(
+    state: &mut Self,
+    _: &ZwlrDataControlDeviceV1,
+    event: <ZwlrDataControlDeviceV1 as Proxy>::Event,
+    global: &u32,
+    conn: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+    let event = match event {
+      zwlr_data_control_device_v1::Event::DataOffer { id } => DeviceEvent::DataOffer(Offer::Wlr(id)),
+      zwlr_data_control_device_v1::Event::Selection { id } => DeviceEvent::Selection(id.map(Offer::Wlr)),
+      zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+        DeviceEvent::PrimarySelection(id.map(Offer::Wlr))
+      }
+      zwlr_data_control_device_v1::Event::Finished => DeviceEvent::Finished,
+      _ => return,
+    };
+
+    state.handle_device_event(conn, *global, event);
+  }
+
+  event_created_child!(
+    WaylandState,
+    ZwlrDataControlDeviceV1,
+    [zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ())]
+  );
+}
+
+impl Dispatch<ExtDataControlOfferV1, ()> for WaylandState {
+  fn event(
+    state: &mut Self,
+    offer: &ExtDataControlOfferV1,
+    event: <ExtDataControlOfferV1 as Proxy>::Event,
+    _: &(),
+    _: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+    if let ext_data_control_offer_v1::Event::Offer { mime_type } = event {
+      state.handle_offer_event(Offer::Ext(offer.clone()), mime_type);
+    }
+  }
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for WaylandState {
+  fn event(
+    state: &mut Self,
+    offer: &ZwlrDataControlOfferV1,
+    event: <ZwlrDataControlOfferV1 as Proxy>::Event,
+    _: &(),
+    _: &Connection,
+    _: &QueueHandle<Self>,
+  ) {
+    if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+      state.handle_offer_event(Offer::Wlr(offer.clone()), mime_type);
+    }
+  }
+}
+
+impl WaylandState {
+  fn handle_device_event(&mut self, conn: &Connection, global: u32, event: DeviceEvent) {
+    let (name, generations) = match self.seats.get(&global) {
+      Some(seat) => (seat.name.clone(), seat.generations.clone()),
+      None => return,
+    };
+
+    match event {
+      DeviceEvent::DataOffer(offer) => {
+        trace!("data offer id: {:?} on seat {:?}", offer.id(), name);
+        self.clipboard.write().unwrap().new_offer(offer.id(), name);
+      }
+
+      DeviceEvent::Selection(Some(offer)) => {
+        trace!("selection id: {:?} on seat {:?}", offer.id(), name);
+        self.selections.changed();
+
+        let generation = generations.next();
+        let live = self.take_live(&offer);
+        if let Some(seat) = self.seats.get_mut(&global) {
+          seat.backoff.reset();
+        }
+        let captured = self.captures(global);
+
+        // an offer without a single mime type has nothing to read, but still has to be released
+        match live {
+          Some(live) if captured && live.offer.is_some() => {
+            let origin = Origin {
+              app_id: self.focus.app_id(),
+              mime_types: live.mime_types.clone(),
+            };
+            spawn_read(conn.clone(), self.clipboard.clone(), live, origin, generation, &self.reads);
+          }
+          _ => {
+            trace!("ignoring selection on seat {:?}", name);
+            offer.destroy();
+          }
+        }
+      }
+      DeviceEvent::Selection(None) => {
+        trace!("selection cleared on seat {:?}", name);
+        generations.next();
+      }
+
+      // the primary selection is not recorded, only released
+      DeviceEvent::PrimarySelection(Some(offer)) => {
+        trace!("primary selection id: {:?}", offer.id());
+        self.take_live(&offer);
+        offer.destroy();
+      }
+      DeviceEvent::PrimarySelection(None) => {}
+
+      // the seat is still there, so keep watching it through a new device. backing off keeps a
+      // compositor that finishes every device from turning this into a busy loop
+      DeviceEvent::Finished => {
+        generations.next();
+        let Some(seat) = self.seats.get_mut(&global) else {
+          return;
+        };
+        if let Some(device) = seat.device.take() {
+          device.destroy();
+        }
+
+        let delay = seat.backoff.next();
+        debug!("data control device for seat {:?} finished by the compositor, recreating it in {:?}", name, delay);
+        seat.recreate_at = Some(Instant::now() + delay);
+      }
+    }
+  }
+
+  /// whether the config asks for this seat's clipboard to be recorded
+  fn captures(&self, global: u32) -> bool {
+    let seats = self.clipboard.read().unwrap().get_config().general.seats;
+    if seats.is_empty() {
+      return true;
+    }
+
+    match self.seats.get(&global).and_then(|seat| seat.name.as_ref()) {
+      Some(name) => seats.contains(name),
+      None => false,
+    }
+  }
+
+  /// give every seat without one a data device, once the manager is known
+  fn create_devices(&mut self) {
+    let manager = match &self.manager {
+      Some(manager) => manager,
+      None => return,
+    };
+
+    let waiting = |seat: &Seat| seat.device.is_none() && seat.recreate_at.is_none();
+    for (global, seat) in self.seats.iter_mut().filter(|(_, seat)| waiting(seat)) {
+      seat.device = Some(manager.get_data_device(&seat.seat, *global, &self.qh));
+    }
+  }
+
+  /// make again the devices the compositor finished, once their backoff has passed
+  fn recreate_devices(&mut self) {
+    let now = Instant::now();
+    let mut due = false;
+    for seat in self.seats.values_mut() {
+      if seat.recreate_at.is_some_and(|at| at <= now) {
+        seat.recreate_at = None;
+        due = true;
+      }
+    }
+
+    if due {
+      self.create_devices();
+    }
+  }
+
+  fn handle_offer_event(&mut self, offer: Offer, mime_type: String) {
+    let mut borrow = self.clipboard.write().unwrap();
+    let live = match borrow.live.get_mut(&offer.id()) {
+      Some(live) => live,
+      None => {
+        debug!("no in progress");
+        return;
+      }
+    };
+
+    live.handle_offer(&offer, mime_type);
+  }
+
+  /// take the in-progress offer the event refers to
+  fn take_live(&self, offer: &Offer) -> Option<clipboard::LiveClipboard> {
+    self.clipboard.write().unwrap().live.remove(&offer.id())
+  }
+}
+
+/// request the selection on a separate thread so a slow or huge source never stalls the event loop
+fn spawn_read(
+  conn: Connection,
+  clipboard: WrappedClipboard,
+  live: clipboard::LiveClipboard,
+  origin: Origin,
+  generation: Generation,
+  reads: &Reads,
+) {
+  let read = reads.start();
+  std::thread::spawn(move || {
+    // ignored selections are never read, so secrets do not even pass through memory
+    let result = match clipboard.read().unwrap().capture(&origin) {
+      Capture::Ignore => {
+        debug!("ignoring a selection from {:?}, as a capture rule says", origin.app_id);
+        Ok(None)
+      }
+      _ => get_item(&conn, &clipboard, &live, &generation),
+    };
+
+    match result {
+      Ok(Some(item)) => {
+        let mut borrow = clipboard.write().unwrap();
+        if generation.is_current() {
+          borrow.commit(item, &origin);
+        } else {
+          debug!("{}", ReadError::Superseded);
+        }
+      }
+      Ok(None) => {}
+      Err(ReadError::Superseded) => debug!("{}", ReadError::Superseded),
+      Err(e) => warn!("failed to read selection: {}", e),
+    }
+
+    if let Some(offer) = &live.offer {
+      offer.destroy();
+      let _ = conn.flush();
+    }
+    drop(read);
+  });
+}
+
+fn receive(conn: &Connection, offer: &Offer, mime_type: &str) -> Result<os_pipe::PipeReader, ReadError> {
+  let (read, write) = os_pipe::pipe()?;
+  offer.receive(mime_type.to_string(), write.as_fd());
+  drop(write);
+
+  conn.flush()?;
+
+  Ok(read)
+}
+
+fn get_item(
+  conn: &Connection,
+  clipboard: &WrappedClipboard,
+  live: &clipboard::LiveClipboard,
+  generation: &Generation,
+) -> Result<Option<clipboard::Item>, ReadError> {
+  let offer = if let Some(offer) = &live.offer {
+    offer
   } else {
-    return None;
+    return Ok(None);
   };
 
-  let offer = live.offer.as_ref().unwrap();
+  let config = clipboard.read().unwrap().get_config();
+  let limits = ReadLimits {
+    timeout: match config.data.read_timeout {
+      0 => None,
+      ms => Some(Duration::from_millis(ms)),
+    },
+    max_size: config.data.max_read_size,
+  };
 
   if let Some(mime_type) = live.mime_types.iter().find(|mime_type| mime_type.starts_with("image/")) {
     debug!("image mime type found: {:?}", mime_type);
 
-    if !state.clipboard.read().unwrap().get_config().general.allow_images {
-      return None;
+    if !config.general.allow_images {
+      return Ok(None);
     }
 
-    let (mut read, write) = os_pipe::pipe().expect("fuck shit");
-    offer.receive((*mime_type).clone(), unsafe {
-      BorrowedFd::borrow_raw(write.as_raw_fd())
-    });
-    drop(write);
-
-    conn.roundtrip().unwrap();
-
-    let mut buffer = vec![];
-    read.read_to_end(&mut buffer).unwrap();
+    let buffer = read_pipe(receive(conn, offer, mime_type)?, &limits, generation)?;
 
     debug!("image buffer size: {:?} bytes", buffer.len());
 
@@ -166,83 +526,42 @@ fn get_item(conn: &Connection, state: &WaylandState) -> Option<clipboard::Item>
 
     if let Some(file_type) = infer::get(&buffer) {
       debug!("file type: {:?} confirmed", file_type);
-      let item = clipboard::Item {
-        id: live.id.to_string(),
-        data: clipboard::ItemData::Image(clipboard::ImageItem {
-          image: buffer,
-          mime: mime_type.to_string(),
-        }),
-      };
-
-      return Some(item);
+      let item = clipboard::Item::new(
+        live.id.to_string(),
+        live.seat.clone(),
+        clipboard::ItemData::Image(clipboard::ImageItem::new(buffer, mime_type.to_string())),
+      );
+
+      return Ok(Some(item));
     }
   }
 
-  let preferred_text_mime = state.clipboard.read().unwrap().preferred_text_mime();
-  let (mut read, write) = os_pipe::pipe().expect("fuck shit");
-
-  offer.receive(preferred_text_mime.clone(), unsafe {
-    BorrowedFd::borrow_raw(write.as_raw_fd())
-  });
-  drop(write);
-
-  conn.roundtrip().unwrap();
-
-  let mut text = String::new();
-  read.read_to_string(&mut text).unwrap();
+  let preferred_text_mime = clipboard.read().unwrap().preferred_text_mime();
+  let buffer = read_pipe(receive(conn, offer, &preferred_text_mime)?, &limits, generation)?;
+  let text = String::from_utf8(buffer).map_err(|_| ReadError::InvalidText)?;
 
-  debug!("text buffer size: {:?} bytes", text.clone().len());
+  debug!("text buffer size: {:?} bytes", text.len());
 
   trace!("wayland data transferred in: {:?}", live.instant.elapsed());
 
   if text.trim().is_empty() {
-    return None;
+    return Ok(None);
   }
 
-  let item = clipboard::Item {
-    id: live.id.to_string(),
-    data: clipboard::ItemData::Text(clipboard::TextItem {
+  let item = clipboard::Item::new(
+    live.id.to_string(),
+    live.seat.clone(),
+    clipboard::ItemData::Text(clipboard::TextItem {
       text,
       mime: preferred_text_mime,
     }),
-  };
-
-  Some(item)
-}
-
-impl Dispatch<ExtDataControlOfferV1, ()> for WaylandState {
-  fn event(
-    state: &mut Self,
-    offer: &ExtDataControlOfferV1,
-    event: <ExtDataControlOfferV1 as Proxy>::Event,
-    _: &(),
-    _: &Connection,
-    _: &QueueHandle<Self>,
-  ) {
-    // println!("offer: {:?}", offer);
-    let mut borrow = state.clipboard.write().unwrap();
-    let live = if let Some(live) = &mut borrow.live {
-      live
-    } else {
-      debug!("no in progress");
-      return;
-    };
-
-    let mime_type = match event {
-      wayland_protocols::ext::data_control::v1::client::ext_data_control_offer_v1::Event::Offer { mime_type } => {
-        mime_type
-      }
-      _ => return,
-    };
+  );
 
-    live.handle_offer(offer, mime_type);
-  }
+  Ok(Some(item))
 }
 
 impl WaylandState {
-  fn new(clipboard: WrappedClipboard) -> (Self, EventQueue<Self>) {
-    let conn = Connection::connect_to_env().unwrap();
-
+  fn new(conn: &Connection, clipboard: WrappedClipboard, watched: Watched) -> Result<(Self, EventQueue<Self>), Error> {
     let display = conn.display();
     let mut queue = conn.new_event_queue();
     let qh: QueueHandle<WaylandState> = queue.handle();
@@ -252,51 +571,235 @@ impl WaylandState {
     let mut state = WaylandState {
       clipboard,
 
-      seat: None,
+      seats: HashMap::new(),
       manager: None,
-      device: None,
+      toplevels: Toplevels::new(),
+      focus: watched.focus,
+      selections: watched.selections,
+      reads: watched.reads,
+      qh,
     };
 
-    // double roundtrip needed for seat to be set
-    queue.roundtrip(&mut state).unwrap();
-    queue.roundtrip(&mut state).unwrap();
+    // double roundtrip needed for seat names to be set
+    queue.roundtrip(&mut state)?;
+    queue.roundtrip(&mut state)?;
 
-    trace!("wayland seat: {:?}", state.seat);
+    trace!("wayland seats: {:?}", state.seats);
     trace!("wayland manager: {:?}", state.manager);
 
-    let seat = state.seat.clone().unwrap();
-    state.device = Some(state.manager.as_ref().unwrap().get_data_device(&seat, &qh, ()));
+    let manager = state.manager.clone().ok_or(Error::MissingProtocol(protocol::ANY_MANAGER))?;
+    if state.seats.is_empty() {
+      return Err(Error::NoSeat);
+    }
+
+    state.create_devices();
 
-    (state, queue)
+    debug!("watching the clipboard through {}", manager.interface());
+
+    Ok((state, queue))
   }
 }
 
+/// delay between reconnect attempts, doubling up to a ceiling
+#[derive(Clone, Debug)]
+struct Backoff {
+  delay: Duration,
+}
+
+impl Backoff {
+  const INITIAL: Duration = Duration::from_millis(250);
+  const MAX: Duration = Duration::from_secs(30);
+
+  fn new() -> Self {
+    Backoff { delay: Self::INITIAL }
+  }
+
+  fn reset(&mut self) {
+    self.delay = Self::INITIAL;
+  }
+
+  /// the current delay, doubling the one after it
+  fn next(&mut self) -> Duration {
+    let delay = self.delay;
+    self.delay = (self.delay * 2).min(Self::MAX);
+
+    delay
+  }
+
+  /// sleep for the current delay, cut short when wayclip is stopping
+  fn wait(&mut self, shutdown: &Shutdown) {
+    shutdown.wait_timeout(self.next());
+  }
+}
+
+/// watch the clipboard until `shutdown` is requested, then wait a little for the selections still
+/// being read so the last save has them
 pub fn watch_clipboard(
   clipboard: WrappedClipboard,
   menu_message_receiver: std::sync::mpsc::Receiver<communication::MPSCMessage>,
+  mut paster: input::Paster,
+  shutdown: Shutdown,
 ) {
-  trace!("Initializing Wayland state and queue");
-  let (mut state, mut queue) = WaylandState::new(clipboard.clone());
-  let mut dev = input::UDevice::new();
+  let watched = Watched {
+    shutdown: shutdown.clone(),
+    ..Watched::default()
+  };
+  let t_watched = watched.clone();
+  let t_clipboard = clipboard.clone();
 
   trace!("Spawning menu message handling thread");
-  std::thread::spawn(move || loop {
-    trace!("Waiting for menu message");
-    let (message, index) = menu_message_receiver.recv().unwrap();
-    trace!("Received menu message: message={}, index={}", message, index);
-    dev.copy(message, clipboard.read().unwrap().get_config().data.mime.clone());
-    trace!("Performed copy operation");
-    dev.paste();
-    trace!("Performed paste operation");
-
-    clipboard.write().unwrap().pasted_idx(index);
-    trace!("Updated clipboard pasted index");
+  // ends once the socket server is gone and the channel closes
+  std::thread::spawn(move || {
+    for message in menu_message_receiver {
+      trace!("Received menu message for item {:?} ({})", message.key, message.mime);
+      handle_message(&t_clipboard, &t_watched, &mut paster, message);
+    }
   });
 
+  let mut backoff = Backoff::new();
+  while !shutdown.requested() {
+    if let Err(e) = connect_and_dispatch(clipboard.clone(), watched.clone(), &mut backoff) {
+      if e.is_fatal() {
+        error!("{}", e);
+        std::process::exit(EXIT_UNSUPPORTED);
+      }
+
+      warn!("{}, reconnecting in {:?}", e, backoff.delay);
+      backoff.wait(&shutdown);
+    }
+  }
+
+  if !watched.reads.wait(READS_TIMEOUT) {
+    warn!("selections were still being read after {:?}, stopping without them", READS_TIMEOUT);
+  }
+}
+
+/// copy a menu selection, then paste or type it out once the copy has landed
+fn handle_message(
+  clipboard: &WrappedClipboard,
+  watched: &Watched,
+  paster: &mut input::Paster,
+  message: communication::MPSCMessage,
+) {
+  // move the item up front first, so that reading back our own copy finds it there already
+  clipboard.write().unwrap().pasted(&message.key);
+  trace!("Updated clipboard pasted index");
+
+  let config = clipboard.read().unwrap().get_config().paste;
+  let action = match message.action {
+    Action::Paste if !config.auto => Action::Copy,
+    action => action,
+  };
+
+  let typed = (action == Action::TypeOut).then(|| typable(&message));
+  let seat = message.seat.clone();
+
+  let seen = watched.selections.current();
+  input::copy(message.data, message.mime, message.seat);
+  trace!("Performed copy operation");
+
+  if action == Action::Copy {
+    return;
+  }
+
+  // the menu may not have given focus back yet, and the application has to see the new selection
+  // before it is told to paste
+  let timeout = Duration::from_millis(config.confirm_timeout);
+  if !watched.selections.wait_past(seen, timeout) {
+    debug!("selection change not seen within {:?}, pasting anyway", timeout);
+  }
+  std::thread::sleep(Duration::from_millis(config.delay));
+
+  let chord = || {
+    let app_id = watched.focus.app_id();
+    let chord = config.chord(app_id.as_deref());
+    trace!("pasting into {:?} with {:?}", app_id, chord);
+    input::Chord::parse(&chord).unwrap_or_else(|| {
+      warn!("could not understand paste chord {:?}", chord);
+      input::Chord::default()
+    })
+  };
+
+  match action {
+    Action::Paste => {
+      paster.paste(&chord(), seat.as_deref());
+      trace!("Performed paste operation");
+    }
+    Action::TypeOut => match typed.unwrap().and_then(|text| type_out(&text, seat.as_deref())) {
+      Ok(()) => trace!("Typed out selection"),
+      Err(e) => {
+        warn!("could not type out the selection, pasting instead: {}", e);
+        paster.paste(&chord(), seat.as_deref());
+      }
+    },
+    _ => {}
+  }
+}
+
+/// the text of a selection to type out, which only works for text
+fn typable(message: &communication::MPSCMessage) -> Result<String, String> {
+  if message.text {
+    Ok(String::from_utf8_lossy(&message.data).into_owned())
+  } else {
+    Err(format!("{} cannot be typed", message.mime))
+  }
+}
+
+/// type text out key by key. it is put on the clipboard as well either way
+fn type_out(text: &str, seat: Option<&str>) -> Result<(), String> {
+  input::keyboard::type_out(text, seat).map_err(|e| e.to_string())
+}
+
+/// run the event loop on a fresh connection until the compositor goes away
+fn connect_and_dispatch(clipboard: WrappedClipboard, watched: Watched, backoff: &mut Backoff) -> Result<(), Error> {
+  let conn = Connection::connect_to_env()?;
+  dispatch(&conn, clipboard, watched, backoff)
+}
+
+/// run the event loop until the compositor goes away, or until wayclip is stopping
+fn dispatch(conn: &Connection, clipboard: WrappedClipboard, watched: Watched, backoff: &mut Backoff) -> Result<(), Error> {
+  trace!("Initializing Wayland state and queue");
+  let shutdown = watched.shutdown.clone();
+  let (mut state, mut queue) = WaylandState::new(conn, clipboard, watched)?;
+  backoff.reset();
+
   trace!("Starting main event loop");
-  loop {
-    trace!("Blocking dispatch start");
-    queue.blocking_dispatch(&mut state).unwrap();
-    trace!("Blocking dispatch completed");
+  while !shutdown.requested() {
+    dispatch_for(&mut queue, &mut state, STOP_CHECK)?;
+    state.recreate_devices();
+  }
+
+  // reads in flight give up once their selection is no longer the newest
+  for seat in state.seats.values() {
+    seat.generations.next();
   }
+
+  Ok(())
+}
+
+/// dispatch the events that arrive within `timeout`, like `blocking_dispatch` but giving up in time
+fn dispatch_for(queue: &mut EventQueue<WaylandState>, state: &mut WaylandState, timeout: Duration) -> Result<(), Error> {
+  queue.dispatch_pending(state)?;
+  queue.flush().map_err(DispatchError::from)?;
+
+  if let Some(guard) = queue.prepare_read() {
+    let mut fd = libc::pollfd {
+      fd: guard.connection_fd().as_raw_fd(),
+      events: libc::POLLIN,
+      revents: 0,
+    };
+
+    // an interrupted poll simply dispatches nothing this time
+    if unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) } > 0 {
+      match guard.read() {
+        Ok(_) => {}
+        Err(WaylandError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+        Err(e) => return Err(DispatchError::from(e).into()),
+      }
+    }
+  }
+
+  queue.dispatch_pending(state)?;
+
+  Ok(())
 }
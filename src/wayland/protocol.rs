@@ -0,0 +1,100 @@
+//! thin wrappers over the two data-control protocols so the rest of wayclip does not care which
+//! one the compositor speaks. ext_data_control_v1 is preferred, zwlr_data_control_v1 is the fallback.
+
+use std::os::fd::BorrowedFd;
+
+use wayland_client::{backend::ObjectId, protocol::wl_seat::WlSeat, Proxy, QueueHandle};
+use wayland_protocols::ext::data_control::v1::client::{
+  ext_data_control_device_v1::ExtDataControlDeviceV1, ext_data_control_manager_v1::ExtDataControlManagerV1,
+  ext_data_control_offer_v1::ExtDataControlOfferV1,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+  zwlr_data_control_device_v1::ZwlrDataControlDeviceV1, zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+  zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+};
+
+use super::WaylandState;
+
+pub const EXT_MANAGER: &str = "ext_data_control_manager_v1";
+pub const WLR_MANAGER: &str = "zwlr_data_control_manager_v1";
+/// how we name the requirement when neither manager is advertised
+pub const ANY_MANAGER: &str = "ext_data_control_v1 or zwlr_data_control_v1";
+
+/// highest zwlr version we understand - v2 added the primary selection
+pub const WLR_VERSION: u32 = 2;
+
+#[derive(Clone, Debug)]
+pub enum Manager {
+  Ext(ExtDataControlManagerV1),
+  Wlr(ZwlrDataControlManagerV1),
+}
+
+impl Manager {
+  pub fn interface(&self) -> &'static str {
+    match self {
+      Manager::Ext(_) => EXT_MANAGER,
+      Manager::Wlr(_) => WLR_MANAGER,
+    }
+  }
+
+  /// create a device for a seat, tagged with the seat's global name
+  pub fn get_data_device(&self, seat: &WlSeat, global: u32, qh: &QueueHandle<WaylandState>) -> Device {
+    match self {
+      Manager::Ext(manager) => Device::Ext(manager.get_data_device(seat, qh, global)),
+      Manager::Wlr(manager) => Device::Wlr(manager.get_data_device(seat, qh, global)),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum Device {
+  Ext(ExtDataControlDeviceV1),
+  Wlr(ZwlrDataControlDeviceV1),
+}
+
+impl Device {
+  pub fn destroy(&self) {
+    match self {
+      Device::Ext(device) => device.destroy(),
+      Device::Wlr(device) => device.destroy(),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Offer {
+  Ext(ExtDataControlOfferV1),
+  Wlr(ZwlrDataControlOfferV1),
+}
+
+impl Offer {
+  pub fn id(&self) -> ObjectId {
+    match self {
+      Offer::Ext(offer) => offer.id(),
+      Offer::Wlr(offer) => offer.id(),
+    }
+  }
+
+  pub fn receive(&self, mime_type: String, fd: BorrowedFd) {
+    match self {
+      Offer::Ext(offer) => offer.receive(mime_type, fd),
+      Offer::Wlr(offer) => offer.receive(mime_type, fd),
+    }
+  }
+
+  pub fn destroy(&self) {
+    match self {
+      Offer::Ext(offer) => offer.destroy(),
+      Offer::Wlr(offer) => offer.destroy(),
+    }
+  }
+}
+
+/// device events common to both protocols
+#[derive(Debug)]
+pub enum DeviceEvent {
+  DataOffer(Offer),
+  Selection(Option<Offer>),
+  PrimarySelection(Option<Offer>),
+  Finished,
+}
@@ -0,0 +1,181 @@
+use std::{
+  fmt,
+  io::{self, Read},
+  os::fd::AsRawFd,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Condvar, Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use wayland_client::backend::WaylandError;
+
+/// how long a single poll waits before checking whether the read was superseded
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum ReadError {
+  Timeout(Duration),
+  TooLarge(usize),
+  Superseded,
+  InvalidText,
+  Io(io::Error),
+  Wayland(WaylandError),
+}
+
+impl fmt::Display for ReadError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ReadError::Timeout(timeout) => write!(f, "source did not finish sending within {:?}", timeout),
+      ReadError::TooLarge(max) => write!(f, "selection is larger than the {} byte limit", max),
+      ReadError::Superseded => write!(f, "read cancelled by a newer selection"),
+      ReadError::InvalidText => write!(f, "text selection is not valid utf-8"),
+      ReadError::Io(e) => write!(f, "io error while reading selection: {}", e),
+      ReadError::Wayland(e) => write!(f, "wayland error while requesting selection: {}", e),
+    }
+  }
+}
+
+impl From<io::Error> for ReadError {
+  fn from(e: io::Error) -> Self {
+    ReadError::Io(e)
+  }
+}
+
+impl From<WaylandError> for ReadError {
+  fn from(e: WaylandError) -> Self {
+    ReadError::Wayland(e)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReadLimits {
+  /// `None` waits for the source forever
+  pub timeout: Option<Duration>,
+  /// 0 means unlimited
+  pub max_size: usize,
+}
+
+/// hands out a new generation for every selection so older reads know they were superseded
+#[derive(Clone, Debug, Default)]
+pub struct Generations(Arc<AtomicU64>);
+
+impl Generations {
+  pub fn next(&self) -> Generation {
+    let mine = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+
+    Generation {
+      current: self.0.clone(),
+      mine,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Generation {
+  current: Arc<AtomicU64>,
+  mine: u64,
+}
+
+impl Generation {
+  pub fn is_current(&self) -> bool {
+    self.current.load(Ordering::SeqCst) == self.mine
+  }
+}
+
+/// counts the selection reads in flight, so stopping can wait for them to end
+#[derive(Clone, Debug, Default)]
+pub struct Reads(Arc<(Mutex<usize>, Condvar)>);
+
+impl Reads {
+  /// count a read until the returned guard is dropped
+  pub fn start(&self) -> ReadGuard {
+    *self.0 .0.lock().unwrap() += 1;
+    ReadGuard(self.clone())
+  }
+
+  /// wait up to `timeout` for every read to end, returning whether they did
+  pub fn wait(&self, timeout: Duration) -> bool {
+    let (running, ended) = &*self.0;
+    let (_running, waited) = (ended)
+      .wait_timeout_while(running.lock().unwrap(), timeout, |running| *running > 0)
+      .unwrap();
+
+    !waited.timed_out()
+  }
+}
+
+/// a read counted by `Reads`, until it is dropped
+pub struct ReadGuard(Reads);
+
+impl Drop for ReadGuard {
+  fn drop(&mut self) {
+    let (running, ended) = &*self.0 .0;
+    *running.lock().unwrap() -= 1;
+    ended.notify_all();
+  }
+}
+
+/// read a pipe to the end, giving up once the limits are hit or a newer selection arrives
+pub fn read_pipe(
+  mut pipe: os_pipe::PipeReader,
+  limits: &ReadLimits,
+  generation: &Generation,
+) -> Result<Vec<u8>, ReadError> {
+  let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+
+  let mut buffer = vec![];
+  let mut chunk = vec![0u8; 64 * 1024];
+
+  loop {
+    if !generation.is_current() {
+      return Err(ReadError::Superseded);
+    }
+
+    let wait = match deadline {
+      Some(deadline) => {
+        let now = Instant::now();
+        if now >= deadline {
+          return Err(ReadError::Timeout(limits.timeout.unwrap_or_default()));
+        }
+
+        (deadline - now).min(POLL_INTERVAL)
+      }
+      None => POLL_INTERVAL,
+    };
+
+    let mut fd = libc::pollfd {
+      fd: pipe.as_raw_fd(),
+      events: libc::POLLIN,
+      revents: 0,
+    };
+
+    let ready = unsafe { libc::poll(&mut fd, 1, wait.as_millis() as libc::c_int) };
+    if ready < 0 {
+      let err = io::Error::last_os_error();
+      if err.kind() == io::ErrorKind::Interrupted {
+        continue;
+      }
+
+      return Err(err.into());
+    }
+
+    if ready == 0 {
+      continue;
+    }
+
+    match pipe.read(&mut chunk) {
+      Ok(0) => return Ok(buffer),
+      Ok(read) => {
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if limits.max_size > 0 && buffer.len() > limits.max_size {
+          return Err(ReadError::TooLarge(limits.max_size));
+        }
+      }
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e.into()),
+    }
+  }
+}
@@ -0,0 +1,41 @@
+//! lets the paste thread wait until a copy has really taken the selection, as seen by our own
+//! data-control device, instead of pressing keys and hoping.
+
+use std::{
+  sync::{Arc, Condvar, Mutex},
+  time::{Duration, Instant},
+};
+
+/// counts selection changes on any seat
+#[derive(Clone, Debug, Default)]
+pub struct Selections(Arc<(Mutex<u64>, Condvar)>);
+
+impl Selections {
+  pub fn current(&self) -> u64 {
+    *self.0 .0.lock().unwrap()
+  }
+
+  pub(super) fn changed(&self) {
+    let (count, changed) = &*self.0;
+    *count.lock().unwrap() += 1;
+    changed.notify_all();
+  }
+
+  /// wait up to `timeout` for the selection to change after `seen` was read. returns whether it did
+  pub fn wait_past(&self, seen: u64, timeout: Duration) -> bool {
+    let (count, changed) = &*self.0;
+    let deadline = Instant::now() + timeout;
+
+    let mut current = count.lock().unwrap();
+    while *current == seen {
+      let left = deadline.saturating_duration_since(Instant::now());
+      if left.is_zero() {
+        return false;
+      }
+
+      current = changed.wait_timeout(current, left).unwrap().0;
+    }
+
+    true
+  }
+}
@@ -0,0 +1,340 @@
+use std::{os::unix::net::UnixStream, thread::JoinHandle, time::Instant};
+
+use tempfile::TempDir;
+use wayland_client::Connection;
+
+use super::{
+  dispatch,
+  error::Error,
+  mock::{wait_for, MockCompositor, Selection},
+  Backoff, Watched,
+};
+use crate::{
+  clipboard::{fixtures, ItemData, WrappedClipboard},
+  config::data::Config,
+};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+struct Harness {
+  compositor: MockCompositor,
+  clipboard: WrappedClipboard,
+  watched: Watched,
+  client: Option<JoinHandle<Result<(), Error>>>,
+  _dir: TempDir,
+}
+
+impl Harness {
+  fn start(config: Config) -> Self {
+    Self::with_seats(config, &["seat0"])
+  }
+
+  fn with_seats(config: Config, seats: &[&str]) -> Self {
+    let harness = Self::connect(config, seats, true);
+    harness.compositor.wait_for_devices(seats.len());
+
+    harness
+  }
+
+  fn connect(config: Config, seats: &[&str], data_control: bool) -> Self {
+    let dir = tempfile::tempdir().unwrap();
+
+    let (clipboard, _) = fixtures::clipboard(dir.path(), config);
+    let (compositor, socket) = MockCompositor::start(seats, data_control);
+    let watched = Watched::default();
+
+    Harness {
+      compositor,
+      client: Some(spawn_client(socket, clipboard.clone(), watched.clone())),
+      clipboard,
+      watched,
+      _dir: dir,
+    }
+  }
+
+  fn texts(&self) -> Vec<String> {
+    let borrow = self.clipboard.read().unwrap();
+    borrow
+      .hist
+      .iter()
+      .filter_map(|item| match &item.data {
+        ItemData::Text(text) => Some(text.text.clone()),
+        ItemData::Image(_) => None,
+      })
+      .collect()
+  }
+
+  fn wait_for_len(&self, len: usize) {
+    assert!(
+      wait_for(|| self.clipboard.read().unwrap().hist.len() >= len),
+      "history never reached {} items",
+      len
+    );
+  }
+
+  /// wait for the wayland client to stop and return why it did
+  fn join(&mut self) -> Error {
+    let client = self.client.take().unwrap();
+    client.join().unwrap().unwrap_err()
+  }
+}
+
+fn spawn_client(socket: UnixStream, clipboard: WrappedClipboard, watched: Watched) -> JoinHandle<Result<(), Error>> {
+  std::thread::spawn(move || {
+    let conn = Connection::from_socket(socket).unwrap();
+    dispatch(&conn, clipboard, watched, &mut Backoff::new())
+  })
+}
+
+fn text(text: &str) -> Selection {
+  Selection::new().with("text/plain", text)
+}
+
+#[test]
+fn records_text_selection() {
+  let harness = Harness::start(Config::default());
+
+  harness.compositor.set_selection(text("hello"));
+  harness.wait_for_len(1);
+
+  let borrow = harness.clipboard.read().unwrap();
+  assert_eq!(borrow.hist[0].seat.as_deref(), Some("seat0"));
+  assert!(matches!(&borrow.hist[0].data, ItemData::Text(text) if text.text == "hello"));
+}
+
+#[test]
+fn reads_the_configured_text_mime() {
+  let mut config = Config::default();
+  config.data.mime = "text/plain;charset=utf-8".to_string();
+  let harness = Harness::start(config);
+
+  let selection = Selection::new()
+    .with("text/plain", "plain")
+    .with("text/plain;charset=utf-8", "utf-8");
+  harness.compositor.set_selection(selection);
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["utf-8"]);
+}
+
+#[test]
+fn skips_images_when_disabled() {
+  let mut config = Config::default();
+  config.general.allow_images = false;
+  let harness = Harness::start(config);
+
+  let selection = Selection::new().with("image/png", PNG).with("text/plain", "alt text");
+  harness.compositor.set_selection(selection);
+  harness.compositor.set_selection(text("after"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["after"]);
+}
+
+#[test]
+fn records_images_when_allowed() {
+  let mut config = Config::default();
+  config.general.allow_images = true;
+  let harness = Harness::start(config);
+
+  let selection = Selection::new().with("image/png", PNG).with("text/plain", "alt text");
+  harness.compositor.set_selection(selection);
+  harness.wait_for_len(1);
+
+  let borrow = harness.clipboard.read().unwrap();
+  match &borrow.hist[0].data {
+    ItemData::Image(image) => {
+      assert_eq!(image.mime, "image/png");
+      assert_eq!(borrow.image_bytes(image).as_deref(), Some(PNG));
+    }
+    ItemData::Text(_) => panic!("expected an image"),
+  }
+}
+
+#[test]
+fn images_get_thumbnails() {
+  let mut config = Config::default();
+  config.general.allow_images = true;
+  config.thumbnails.size = 64;
+  let harness = Harness::start(config);
+
+  let mut png = std::io::Cursor::new(vec![]);
+  image::RgbImage::new(320, 200)
+    .write_to(&mut png, image::ImageFormat::Png)
+    .unwrap();
+  harness.compositor.set_selection(Selection::new().with("image/png", png.into_inner()));
+  harness.wait_for_len(1);
+
+  let image = match &harness.clipboard.read().unwrap().hist[0].data {
+    ItemData::Image(image) => image.clone(),
+    ItemData::Text(_) => panic!("expected an image"),
+  };
+  assert_eq!(harness.clipboard.read().unwrap().image_dimensions(&image), Some((320, 200)));
+
+  assert!(wait_for(|| harness.clipboard.read().unwrap().thumbnail(&image).is_some()));
+  let thumbnail = harness.clipboard.read().unwrap().thumbnail(&image).unwrap();
+  assert_eq!(image::image_dimensions(thumbnail).unwrap(), (64, 40));
+}
+
+#[test]
+fn ignores_primary_selection() {
+  let harness = Harness::start(Config::default());
+
+  harness.compositor.set_primary_selection(text("primary"));
+  harness.compositor.set_selection(text("regular"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["regular"]);
+}
+
+#[test]
+fn newer_selection_supersedes_stalled_read() {
+  let mut config = Config::default();
+  config.data.read_timeout = 60_000;
+  let harness = Harness::start(config);
+
+  harness.compositor.set_selection(text("stuck").stalled());
+  harness.compositor.set_selection(text("fresh"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["fresh"]);
+}
+
+#[test]
+fn stalled_read_times_out() {
+  let mut config = Config::default();
+  config.data.read_timeout = 50;
+  let harness = Harness::start(config);
+
+  harness.compositor.set_selection(text("stuck").stalled());
+  std::thread::sleep(std::time::Duration::from_millis(200));
+  harness.compositor.set_selection(text("next"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["next"]);
+}
+
+#[test]
+fn oversized_selection_is_dropped() {
+  let mut config = Config::default();
+  config.data.max_read_size = 8;
+  let harness = Harness::start(config);
+
+  harness.compositor.set_selection(text("far more than eight bytes"));
+  harness.compositor.set_selection(text("small"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["small"]);
+}
+
+#[test]
+fn records_only_configured_seats() {
+  let mut config = Config::default();
+  config.general.seats = vec!["seat1".to_string()];
+  let harness = Harness::with_seats(config, &["seat0", "seat1"]);
+
+  harness.compositor.set_selection_on("seat0", text("ignored"));
+  harness.compositor.set_selection_on("seat1", text("recorded"));
+  harness.wait_for_len(1);
+
+  let borrow = harness.clipboard.read().unwrap();
+  assert_eq!(borrow.hist.len(), 1);
+  assert_eq!(borrow.hist[0].seat.as_deref(), Some("seat1"));
+}
+
+#[test]
+fn disconnect_is_recoverable() {
+  let mut harness = Harness::start(Config::default());
+
+  harness.compositor.disconnect();
+  let e = harness.join();
+
+  assert!(matches!(e, Error::Dispatch(_)), "unexpected error: {}", e);
+  assert!(!e.is_fatal());
+}
+
+#[test]
+fn offers_are_released_even_without_mime_types() {
+  let harness = Harness::start(Config::default());
+
+  harness.compositor.set_selection(Selection::new());
+  harness.compositor.set_selection(text("after"));
+  harness.wait_for_len(1);
+
+  harness.compositor.wait_for_offers_destroyed();
+  assert_eq!(harness.texts(), vec!["after"]);
+}
+
+#[test]
+fn stopping_ends_the_event_loop() {
+  let mut harness = Harness::start(Config::default());
+
+  harness.watched.shutdown.request();
+  let client = harness.client.take().unwrap();
+
+  assert!(client.join().unwrap().is_ok());
+  assert!(harness.watched.reads.wait(std::time::Duration::from_secs(1)));
+}
+
+#[test]
+fn finished_devices_are_recreated() {
+  let harness = Harness::start(Config::default());
+
+  harness.compositor.finish_devices();
+  harness.compositor.wait_for_devices(2);
+  harness.compositor.set_selection(text("after"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["after"]);
+}
+
+#[test]
+fn finished_devices_are_recreated_with_a_growing_delay() {
+  let harness = Harness::start(Config::default());
+  let recreated = |devices: usize| {
+    let start = Instant::now();
+    harness.compositor.finish_devices();
+    harness.compositor.wait_for_devices(devices);
+    start.elapsed()
+  };
+
+  assert!(recreated(2) >= Backoff::INITIAL);
+  assert!(recreated(3) >= Backoff::INITIAL * 2);
+}
+
+#[test]
+fn missing_data_control_is_fatal() {
+  let mut harness = Harness::connect(Config::default(), &["seat0"], false);
+
+  let e = harness.join();
+
+  assert!(matches!(e, Error::MissingProtocol(_)), "unexpected error: {}", e);
+  assert!(e.is_fatal());
+}
+
+#[test]
+fn selection_changes_are_signalled() {
+  let mut config = Config::default();
+  config.general.seats = vec!["seat1".to_string()];
+  let harness = Harness::with_seats(config, &["seat0", "seat1"]);
+  let selections = &harness.watched.selections;
+
+  // even on a seat that is not recorded, a paste there still has to wait for it
+  let seen = selections.current();
+  harness.compositor.set_selection_on("seat0", text("hello"));
+
+  assert!(selections.wait_past(seen, std::time::Duration::from_secs(5)));
+  assert!(!selections.wait_past(selections.current(), std::time::Duration::from_millis(50)));
+}
+
+#[test]
+fn skips_selections_marked_as_secret() {
+  let harness = Harness::start(Config::default());
+
+  let secret = text("hunter2").with("x-kde-passwordManagerHint", "secret");
+  harness.compositor.set_selection(secret);
+  harness.compositor.set_selection(text("after"));
+  harness.wait_for_len(1);
+
+  assert_eq!(harness.texts(), vec!["after"]);
+}